@@ -1,4 +1,4 @@
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, Parser, Subcommand};
 
 
 const ABOUT_CLI: &str = "Screens your Git projects for unstaged, untracked files \
@@ -11,14 +11,40 @@ const ABOUT_CLI: &str = "Screens your Git projects for unstaged, untracked files
                         |    ✓    |   +   | Project up to date                    |\n\
                         |    →    |   ->  | Ahead origin                          |\n\
                         |    ←    |   <-  | Behind origin                         |\n\
-                        |    ◎    |   *   | Unstaged/Untracked files (pre commit) |\n\
+                        |    ⊘    |   @   | Detached HEAD                         |\n\
+                        |    ⎇    |   &   | Other branches with unpushed commits  |\n\
+                        |    ⇡    |   ^   | No upstream configured (never pushed) |\n\
+                        |    ●    |   S   | Staged files                          |\n\
+                        |    ◎    |   *   | Modified files (not staged)           |\n\
+                        |    ⊖    |   D   | Deleted files                         |\n\
+                        |    ⇄    |   R   | Renamed files                         |\n\
+                        |    ◌    |   ?   | Untracked files                       |\n\
+                        |    ‼    |   U   | Unmerged files (merge conflict)       |\n\
+                        |    ⚑    |   $   | Stashed changes                       |\n\
+                        |    ⊞    |   #   | Submodules with changes               |\n\
+                        |    ▣    |   B   | Bare repository or mirror             |\n\
+                        |    ○    |   -   | Submodule not initialized             |\n\
+                        |    ±    |   %   | Submodule at a different commit       |\n\
+                        |    ⋈    |   m!  | Merge in progress                     |\n\
+                        |    ↻    |   r!  | Rebase in progress                    |\n\
+                        |    ⤳    |   c!  | Cherry-pick in progress               |\n\
+                        |    ↶    |   v!  | Revert in progress                    |\n\
+                        |    ½    |   b!  | Bisect in progress                    |\n\
+                        |    ≈    |   ~   | Remote not fetched, may be outdated   |\n\
+                        |    ⧖    |   t   | Fetch timed out                       |\n\
                         |    ⨯    |   x   | Error occured                         |\n\
                         +---------+-------+---------------------------------------+\n\
+                        The arrows are followed by the number of commits, e.g. `[→3 ←12]`.\n\
+                        Without upstream, `⇡` is followed by the number of commits, which are not on\n\
+                        the default branch of `origin`, e.g. `[⇡2]`.\n\
+                        Optionally, the file indicators are followed by the number of files, e.g. `[●1 ◎4]`.\n\
                         \n\
                         Note on the output stream:\n\
                         \tAll handled errors are streamed to stderr. So you're able \
                         to pipe stdout to `wc -l`,\n\
-                        \tand get the count of all found Git projects.";
+                        \tand get the count of all found Git projects. Submodules, branches and \
+                        worktrees are\n\
+                        \tlisted below their project on stdout, and start with a space.";
 
 #[derive(Parser)]
 #[command(name = "GST (Status for Git)")]
@@ -37,11 +63,23 @@ pub struct Cli {
     pub paths: Option<String>,
 
     /// Displays more information:
-    ///     `-v`: Show projects, that are up to date.
-    ///     `-vv`: Additionally, show the absolute path per project.
+    ///     `-v`: Show projects, that are up to date, and the number of stash entries.
+    ///     `-vv`: Additionally, show the absolute path per project, and list branches with unpushed commits.
     #[arg(short = 'v', long = "verbose", action = ArgAction::Count, verbatim_doc_comment)]
     pub verbose: u8,
 
+    /// Signals the result via the exit code:
+    ///     `0`: All projects are up to date.
+    ///     `1`: At least one project has changes, or commits ahead/behind.
+    ///     `2`: An error occurred, e.g. a failed fetch.
+    ///     `3`: The configuration or an argument is invalid.
+    #[arg(long, verbatim_doc_comment)]
+    pub exit_code: bool,
+
+    /// Prints nothing, and only signals the result via the exit code. Implies `--exit-code`.
+    #[arg(short, long)]
+    pub quiet: bool,
+
     /// Config: Removes a single project path from the configuration.
     #[arg(short, long)]
     pub remove_path: Option<String>,
@@ -50,12 +88,101 @@ pub struct Cli {
     #[arg(long)]
     pub purge_paths: bool,
 
+    /// Config: Skips directories matching a gitignore-style glob pattern.
+    ///     Example: `-e node_modules -e "vendor/**"`
+    ///     Patterns can also be put in a `.gstignore` file inside each project path.
+    #[arg(short, long, action = ArgAction::Append, verbatim_doc_comment)]
+    pub exclude: Option<Vec<String>>,
+
+    /// Config: Removes a single exclude pattern from the configuration.
+    #[arg(long)]
+    pub remove_exclude: Option<String>,
+
+    /// Config: Keeps searching inside a found project for further, independent projects.
+    ///     Otherwise the search stops at the root of a project.
+    ///     Example: `gst --nested-repos true`
+    ///     Default: `--nested-repos false`
+    #[arg(long, verbatim_doc_comment)]
+    pub nested_repos: Option<String>,
+
+    /// Config: Follows symlinked directories during the search.
+    ///     Projects reached more than once are only reported once.
+    ///     Example: `gst --follow-symlinks false`
+    ///     Default: `--follow-symlinks true`
+    #[arg(long, verbatim_doc_comment)]
+    pub follow_symlinks: Option<String>,
+
     /// Config: Uses ASCII characters as status indicators, instead of UTF8/Unicode characters.
     ///     Example: `gst --ascii-mode true` -> uses ASCII characters instead.
     ///     Default: `--ascii-mode false`
     #[arg(short, long , verbatim_doc_comment)]
     pub ascii_mode: Option<String>,
 
+    /// Config: Shows the number of commits ahead/behind, e.g. `[→3 ←12]`.
+    ///     Example: `gst --counts false` -> only shows the arrows, e.g. `[→←]`.
+    ///     Default: `--counts true`
+    #[arg(long, verbatim_doc_comment)]
+    pub counts: Option<String>,
+
+    /// Config: Shows the number of files per file indicator, e.g. `[●1 ◎4]`.
+    ///     Example: `gst --file-counts true`
+    ///     Default: `--file-counts false`
+    #[arg(long, verbatim_doc_comment)]
+    pub file_counts: Option<String>,
+
+    /// Config: Checks every local branch for unpushed commits, not only the checked out one.
+    ///     Example: `gst --all-branches true`
+    ///     Default: `--all-branches false`
+    #[arg(long, verbatim_doc_comment)]
+    pub all_branches: Option<String>,
+
+    /// Checks every local branch for unpushed commits, only for this run
+    #[arg(short, long)]
+    pub branches: bool,
+
+    /// Config: Runs `git fetch` for each project, before its status is checked.
+    ///     Example: `gst --fetch false` -> never contacts the remotes.
+    ///     Default: `--fetch true`
+    #[arg(long, verbatim_doc_comment)]
+    pub fetch: Option<String>,
+
+    /// Config: Seconds until `git fetch` is cancelled. `0` waits forever (Default: 30)
+    #[arg(long)]
+    pub fetch_timeout: Option<u64>,
+
+    /// Config: Overrides the fetch timeout for a single, configured project path.
+    ///     Example: `gst --path-fetch-timeout "/home/usr/slow_remotes=120"`
+    #[arg(long, verbatim_doc_comment)]
+    pub path_fetch_timeout: Option<String>,
+
+    /// Keeps running, and updates the output as soon as a project changes
+    #[arg(short, long)]
+    pub watch: bool,
+
+    /// Config: Seconds between fetches in watch mode. `0` only fetches at the start (Default: 300)
+    #[arg(long)]
+    pub watch_fetch_interval: Option<u64>,
+
+    /// Skips `git fetch` for this run, and uses the last known state of the remotes
+    #[arg(long, visible_alias = "offline")]
+    pub no_fetch: bool,
+
+    /// The output format: `text`, `json` or `ndjson` (Default: text)
+    ///     `json`: One array with a record per project, printed at the end.
+    ///     `ndjson`: One record per line, printed as soon as a project is checked.
+    #[arg(short, long, verbatim_doc_comment)]
+    pub format: Option<String>,
+
+    /// Searches all project paths again, instead of using the discovery cache
+    #[arg(long)]
+    pub rescan: bool,
+
+    /// Reads and writes the configuration from this file, instead of the default one.
+    ///     Example: `gst --config ~/dotfiles/gst.json`
+    ///     Default: `$GST_CONFIG`, `$XDG_CONFIG_HOME/gst/gst.json` or `$HOME/.config/gst/gst.json`
+    #[arg(long, global = true, verbatim_doc_comment)]
+    pub config: Option<String>,
+
     /// Displays the current configuration
     #[arg(short, long)]
     pub show_config: bool,
@@ -64,8 +191,36 @@ pub struct Cli {
     #[arg(long)]
     pub search_depth: Option<i8>,
 
+    /// Config: The number of projects, that are checked in parallel, from 1 to 64 (Default: 8)
+    #[arg(short, long)]
+    pub jobs: Option<u8>,
+
     /// Executes this program inside the current folder, without saving the path to the
     /// configuration
     #[arg(short, long)]
     pub current_dir: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manages the configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Migrates a configuration file of a previous major version. The old file is kept as a backup.
+    ///     This also happens automatically, as soon as `gst` runs.
+    #[command(verbatim_doc_comment)]
+    Migrate {
+        /// Prints the migrated configuration, without writing it
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
@@ -0,0 +1,135 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Serialize, Deserialize};
+
+use crate::discovery::Repository;
+
+
+// Bumped, whenever the layout changes. Older caches are discarded.
+const CACHE_VERSION: u32 = 1;
+
+// The repositories found below each project path. Stored at `Config::cache_path`, so the
+// directory trees don't have to be walked again on every run.
+#[derive(Serialize, Deserialize, Default)]
+pub struct DiscoveryCache {
+    version: u32,
+    projects: BTreeMap<PathBuf, CachedProject>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    changed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct CachedProject {
+    // Exclude patterns, nesting mode, etc. the repositories were discovered with
+    pub settings: String,
+    // Modification time of every searched directory. Adding or removing an entry changes it.
+    pub mtimes: BTreeMap<PathBuf, u64>,
+    pub repositories: Vec<CachedRepository>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct CachedRepository {
+    pub work_tree: PathBuf,
+    pub bare: bool,
+}
+
+impl CachedRepository {
+    pub fn from_repository(repository: &Repository) -> CachedRepository {
+        CachedRepository {
+            work_tree: repository.work_tree.clone(),
+            bare: repository.bare,
+        }
+    }
+
+    // The `.git` of a work tree, or the bare repository itself
+    pub fn git_path(&self) -> PathBuf {
+        match self.bare {
+            true => self.work_tree.clone(),
+            false => self.work_tree.join(".git"),
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.git_path().exists()
+    }
+}
+
+impl CachedProject {
+    // Nothing was added or removed in any of the searched directories
+    pub fn is_valid(&self, settings: &str) -> bool {
+        self.settings == settings && self.mtimes.iter().all(|(path, v)| mtime(path) == *v)
+    }
+}
+
+impl DiscoveryCache {
+    // A missing, corrupted or outdated cache is treated as empty
+    pub fn load(path: PathBuf) -> DiscoveryCache {
+        let cache = fs::read_to_string(&path)
+            .ok()
+            .and_then(|v| serde_json::from_str::<DiscoveryCache>(&v).ok())
+            .filter(|v| v.version == CACHE_VERSION);
+
+        match cache {
+            Some(v) => DiscoveryCache { path, ..v },
+            None => DiscoveryCache {
+                version: CACHE_VERSION,
+                projects: BTreeMap::new(),
+                path,
+                changed: false,
+            },
+        }
+    }
+
+    pub fn get(&self, project: &Path) -> Option<&CachedProject> {
+        self.projects.get(project)
+    }
+
+    pub fn insert(&mut self, project: &Path, cached: CachedProject) -> &mut Self {
+        if self.projects.get(project) != Some(&cached) {
+            self.projects.insert(project.to_path_buf(), cached);
+            self.changed = true;
+        }
+        self
+    }
+
+    pub fn remove(&mut self, project: &Path) -> &mut Self {
+        self.changed |= self.projects.remove(project).is_some();
+        self
+    }
+
+    // Drops the project paths, which were removed from the configuration
+    pub fn retain(&mut self, projects: &[PathBuf]) -> &mut Self {
+        let count = self.projects.len();
+        self.projects.retain(|path, _| projects.contains(path));
+        self.changed |= count != self.projects.len();
+        self
+    }
+
+    pub fn write(&self) -> Result<(), String> {
+        if !self.changed {
+            return Ok(());
+        }
+        let serialized = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        if let Some(cache_dir) = self.path.parent() {
+            fs::create_dir_all(cache_dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(&self.path, serialized).map_err(|e| e.to_string())
+    }
+}
+
+// Nanoseconds since the epoch, or 0 if the path doesn't exist
+pub fn mtime(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|v| v.modified())
+        .ok()
+        .and_then(|v| v.duration_since(UNIX_EPOCH).ok())
+        .map(|v| v.as_nanos() as u64)
+        .unwrap_or(0)
+}
@@ -5,22 +5,27 @@ pub struct TerminalColor {
     pub red: &'static str,
     pub green: &'static str,
     pub yellow: &'static str,
+    pub magenta: &'static str,
+    pub cyan: &'static str,
 }
 
 pub enum Color {
     Red,
     Green,
     Yellow,
+    Magenta,
+    Cyan,
 }
 
 impl TerminalColor {
     pub fn new() -> TerminalColor {
-        let terminal_color = TerminalColor {
+        TerminalColor {
             red: "\x1b[31m",
             green: "\x1b[32m",
             yellow: "\x1b[33m",
-        };
-        terminal_color
+            magenta: "\x1b[35m",
+            cyan: "\x1b[36m",
+        }
     }
 
     pub fn color(&self, msg: &str, color: Color) -> String {
@@ -29,6 +34,8 @@ impl TerminalColor {
             Color::Red => format!("{}{}{}", self.red, &msg, TAIL),
             Color::Green => format!("{}{}{}", self.green, &msg, TAIL),
             Color::Yellow => format!("{}{}{}", self.yellow, &msg, TAIL),
+            Color::Magenta => format!("{}{}{}", self.magenta, &msg, TAIL),
+            Color::Cyan => format!("{}{}{}", self.cyan, &msg, TAIL),
         }
     }
 }
@@ -1,126 +1,188 @@
 use std::{
-    env,
-    path::PathBuf,
-    io::{Read, Write},
-    fs::{self, File, OpenOptions},
-    process::exit,
+    env, fs,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
 };
 
 use serde::{Serialize, Deserialize};
 
+use crate::error::GstError;
+use crate::migrate::{self, Migration};
+
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Config {
     pub version: String,
     pub search_depth: i8,
     pub ascii_mode: bool,
+    #[serde(default = "default_show_counts")]
+    pub show_counts: bool,
+    #[serde(default)]
+    pub show_file_counts: bool,
+    #[serde(default = "default_jobs")]
+    pub jobs: u8,
+    #[serde(default = "default_fetch")]
+    pub fetch: bool,
+    #[serde(default)]
+    pub all_branches: bool,
+    #[serde(default = "default_fetch_timeout")]
+    pub fetch_timeout: u64,
+    #[serde(default)]
+    pub path_fetch_timeouts: BTreeMap<PathBuf, u64>,
+    #[serde(default = "default_watch_fetch_interval")]
+    pub watch_fetch_interval: u64,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub nested_repositories: bool,
+    #[serde(default = "default_follow_symlinks")]
+    pub follow_symlinks: bool,
     pub project_paths: Vec<PathBuf>,
+    // The file, the configuration was read from
+    #[serde(skip)]
+    pub(crate) path: PathBuf,
 }
 
-impl Config {
-    fn defaults() -> Config {
-        let config = Config {
-            version: option_env!("CARGO_PKG_VERSION").unwrap_or("UNKNOWN").to_string(),
+fn default_show_counts() -> bool {
+    true
+}
+
+fn default_jobs() -> u8 {
+    8
+}
+
+fn default_fetch() -> bool {
+    true
+}
+
+fn default_fetch_timeout() -> u64 {
+    30
+}
+
+fn default_watch_fetch_interval() -> u64 {
+    300
+}
+
+fn default_follow_symlinks() -> bool {
+    true
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            version: env!("CARGO_PKG_VERSION").to_string(),
             search_depth: 5,
             ascii_mode: false,
+            show_counts: default_show_counts(),
+            show_file_counts: false,
+            jobs: default_jobs(),
+            fetch: default_fetch(),
+            all_branches: false,
+            fetch_timeout: default_fetch_timeout(),
+            path_fetch_timeouts: BTreeMap::new(),
+            watch_fetch_interval: default_watch_fetch_interval(),
+            exclude: vec![],
+            nested_repositories: false,
+            follow_symlinks: default_follow_symlinks(),
             project_paths: vec![],
-        };
-        config
+            path: PathBuf::new(),
+        }
     }
+}
 
-    pub fn new() -> Config {
-        let config_file = Self::check_config();
-        let config_fp = &config_file.0;
-        let is_config_missing = &config_file.1;
+impl Config {
+    // Reads the config file at its default location, see `Config::default_path`
+    pub fn new() -> Result<Config, GstError> {
+        Self::open(&Self::default_path()?)
+    }
 
-        if *is_config_missing {
-            let defaults = Config::defaults();
-            write_json(config_fp.to_path_buf(), &defaults);
+    // Reads the config file at `config_fp`. It's created with the defaults, if it's missing.
+    // A config file of a previous major version is migrated, and the old one is kept.
+    pub fn open(config_fp: &Path) -> Result<Config, GstError> {
+        Self::create(config_fp)?;
+        let migration = Self::migration(config_fp)?;
+        if migration.is_needed() {
+            migration.apply()?;
         }
+        Ok(migration.config)
+    }
 
-        let mut file = match File::open(config_fp) {
-            Ok(fh) => fh,
-            Err(e) => {
-                dbg!(e);
-                exit(1);
-            }
-        };
-        let mut buf = String::new();
-        match file.read_to_string(&mut buf) {
-            Ok(..) => (),
-            Err(e) => {
-                dbg!(e);
-                exit(1);
-            }
-        };
-        let deserialized: Config = match serde_json::from_str(&buf) {
-            Ok(v) => v,
-            Err(_) => {
-                eprintln!("Config file seems to be empty or corrupted. Delete the file and run the program again.");
-                exit(1);
-            }
-        };
-        deserialized
+    // Writes the defaults to `config_fp`, including its directories, unless the file exists.
+    // Returns, whether it was created.
+    pub fn create(config_fp: &Path) -> Result<bool, GstError> {
+        if config_fp.is_file() {
+            return Ok(false);
+        }
+        if let Some(config_dir) = config_fp.parent().filter(|v| !v.as_os_str().is_empty()) {
+            fs::create_dir_all(config_dir)
+                .map_err(|e| GstError::ConfigWrite(config_dir.to_path_buf(), e.to_string()))?;
+        }
+        write_json(config_fp, &Config::default())?;
+        Ok(true)
+    }
+
+    // The config file, migrated to the current version, but not written yet
+    pub fn migration(config_fp: &Path) -> Result<Migration, GstError> {
+        let buf = fs::read_to_string(config_fp)
+            .map_err(|e| GstError::ConfigRead(config_fp.to_path_buf(), e.to_string()))?;
+        let mut migration = migrate::migrate(config_fp, &buf)?;
+        if let Ok(gst_dir) = Self::gst_dir() {
+            migration.backup_dir = gst_dir;
+        }
+        Ok(migration)
+    }
+
+    // The first one of:
+    // - `$GST_CONFIG`
+    // - `$XDG_CONFIG_HOME/gst/gst.json`
+    // - `$HOME/.config/gst/gst.json`, or `%USERPROFILE%/.config/gst/gst.json` on Windows
+    pub fn default_path() -> Result<PathBuf, GstError> {
+        if let Some(config_fp) = env_path("GST_CONFIG") {
+            return Ok(config_fp);
+        }
+        Ok(Self::gst_dir()?.join("gst.json"))
+    }
+
+    // The `gst` directory of the default config file. It holds the backups of migrated config
+    // files, even of the ones at another location, so a dotfiles repository stays untouched.
+    pub fn gst_dir() -> Result<PathBuf, GstError> {
+        Ok(Self::config_dir()?.join("gst"))
     }
 
-    fn config_dir() -> PathBuf {
+    fn config_dir() -> Result<PathBuf, GstError> {
+        // Relative paths are invalid, according to the XDG Base Directory Specification
+        if let Some(xdg_config_home) = env_path("XDG_CONFIG_HOME").filter(|v| v.is_absolute()) {
+            return Ok(xdg_config_home);
+        }
+
         #[cfg(target_family = "windows")]
         let home_env_var = "USERPROFILE";
 
         #[cfg(target_family = "unix")]
         let home_env_var = "HOME";
 
-        let home_dir = match env::var(home_env_var) {
-            Ok(v) => v,
-            Err(e) => {
-                dbg!(e);
-                exit(1);
-            },
-        };
+        let home_dir = env_path(home_env_var).ok_or(GstError::HomeNotSet(home_env_var))?;
 
-        let mut config_fp = PathBuf::new();
-        config_fp.push(home_dir);
-        config_fp.push(".config");
-        config_fp
+        let mut config_dir = PathBuf::new();
+        config_dir.push(home_dir);
+        config_dir.push(".config");
+        Ok(config_dir)
     }
 
-    fn check_config() -> (PathBuf, bool) {
-        // check for config dir
-        let pbuf = Self::config_dir();
-        if !pbuf.try_exists().unwrap() {
-            eprintln!("Can not find directory `{}`", pbuf.to_str().unwrap());
-            exit(1);
-        };
-
-        // check for gst dir
-        let mut gst_dir = PathBuf::new();
-        gst_dir.push(pbuf);
-        gst_dir.push("gst");
-
-        if !gst_dir.try_exists().unwrap() {
-            eprintln!("'gst' config dir does not exist. Creating it ...");
-            match fs::create_dir(&gst_dir) {
-                Ok(()) => (),
-                Err(e) => {
-                    dbg!(e);
-                    exit(1);
-                }
-            }
-        }
-
-        // check for gst config file
-        let mut config_fp = PathBuf::new();
-        config_fp.push(gst_dir);
-        config_fp.push("gst.json");
-
-        let mut missing_config_file = false;
+    // The file, the configuration was read from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
 
-        if !config_fp.is_file() {
-            eprintln!("'gst' config file does not exist. Creating it ...");
-            missing_config_file = true;
-            let _ = File::create_new(&config_fp);
+    // `$XDG_CACHE_HOME/gst/cache.json`, or `cache.json` in `Config::gst_dir`. The cache contains
+    // paths and modification times of this machine, so it's never stored next to a config
+    // file given by `--config` or `$GST_CONFIG`.
+    pub fn cache_path() -> Result<PathBuf, GstError> {
+        let cache_dir = match env_path("XDG_CACHE_HOME").filter(|v| v.is_absolute()) {
+            Some(xdg_cache_home) => xdg_cache_home.join("gst"),
+            None => Self::gst_dir()?,
         };
-        (config_fp, missing_config_file)
+        Ok(cache_dir.join("cache.json"))
     }
 
     pub fn show_config(&mut self) -> &mut Self {
@@ -129,11 +191,9 @@ impl Config {
         self
     }
 
-    pub fn write_config(&mut self) -> () {
-        let config_file = Self::check_config();
-        write_json(config_file.0, self);
-        println!("[*] Updated configuration");
-        exit(0);
+    pub fn write_config(&mut self) -> Result<&mut Self, GstError> {
+        write_json(&self.path, self)?;
+        Ok(self)
     }
 
     pub fn add_path(&mut self, fp: &str) -> &mut Self {
@@ -145,15 +205,28 @@ impl Config {
     }
 
     pub fn remove_path(&mut self, fp: &str) -> &mut Self {
-        match found_path_in_paths(&self.project_paths, fp) {
-            true => self.project_paths.retain(|x| *x.to_str().unwrap() != *fp),
-            false => (),
+        if found_path_in_paths(&self.project_paths, fp) {
+            self.project_paths.retain(|x| *x.to_str().unwrap() != *fp);
         }
+        self.path_fetch_timeouts.remove(&PathBuf::from(fp));
         self
     }
 
     pub fn purge_paths(&mut self) -> &mut Self {
         self.project_paths = Vec::new();
+        self.path_fetch_timeouts = BTreeMap::new();
+        self
+    }
+
+    pub fn add_exclude(&mut self, pattern: &str) -> &mut Self {
+        if !self.exclude.iter().any(|v| v == pattern) {
+            self.exclude.push(pattern.to_string());
+        }
+        self
+    }
+
+    pub fn remove_exclude(&mut self, pattern: &str) -> &mut Self {
+        self.exclude.retain(|v| v != pattern);
         self
     }
 
@@ -162,39 +235,82 @@ impl Config {
         self
     }
 
+    pub fn counts_enabled(&mut self, show_counts: bool) -> &mut Self {
+        self.show_counts = show_counts;
+        self
+    }
+
+    pub fn file_counts_enabled(&mut self, show_file_counts: bool) -> &mut Self {
+        self.show_file_counts = show_file_counts;
+        self
+    }
+
     pub fn search_depth(&mut self, depth: i8) -> &mut Self {
         self.search_depth = depth;
         self
     }
-}
 
-fn write_json(file_ref: PathBuf, content: &Config) -> () {
-    let mut file = match OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(file_ref) {
-            Ok(fh) => fh,
-            Err(e) => {
-                dbg!(e);
-                exit(1);
-            }
-        };
+    pub fn jobs(&mut self, jobs: u8) -> &mut Self {
+        self.jobs = jobs;
+        self
+    }
+
+    pub fn fetch_timeout(&mut self, seconds: u64) -> &mut Self {
+        self.fetch_timeout = seconds;
+        self
+    }
+
+    pub fn watch_fetch_interval(&mut self, seconds: u64) -> &mut Self {
+        self.watch_fetch_interval = seconds;
+        self
+    }
 
-    let serialized = serde_json::to_string_pretty(&content).unwrap();
-    let buf = serialized.as_bytes();
+    pub fn path_fetch_timeout(&mut self, fp: &str, seconds: u64) -> &mut Self {
+        self.path_fetch_timeouts.insert(fp.into(), seconds);
+        self
+    }
 
-    match file.write(buf) {
-        Ok(..) => (),
-        Err(e) => {
-            eprintln!("Could not write into buffer: {e}");
-            exit(1);
+    // Seconds until `git fetch` gets cancelled for projects below `fp`. 0 means no limit.
+    // Paths are compared by their components, so a trailing `/` makes no difference.
+    pub fn fetch_timeout_for(&self, fp: &Path) -> u64 {
+        match self.path_fetch_timeouts.iter().find(|(v, _)| v.as_path() == fp).map(|(_, v)| v) {
+            Some(v) => *v,
+            None => self.fetch_timeout,
         }
     }
-}
 
-fn found_path_in_paths(paths: &Vec<PathBuf>, fp: &str) -> bool {
-    if paths.iter().any(|i| i.to_str().unwrap() == fp) {
-        return true
+    pub fn all_branches_enabled(&mut self, all_branches_enabled: bool) -> &mut Self {
+        self.all_branches = all_branches_enabled;
+        self
     }
-    return false
+
+    pub fn nested_repositories_enabled(&mut self, nested_repositories_enabled: bool) -> &mut Self {
+        self.nested_repositories = nested_repositories_enabled;
+        self
+    }
+
+    pub fn follow_symlinks_enabled(&mut self, follow_symlinks_enabled: bool) -> &mut Self {
+        self.follow_symlinks = follow_symlinks_enabled;
+        self
+    }
+
+    pub fn fetch_enabled(&mut self, fetch_enabled: bool) -> &mut Self {
+        self.fetch = fetch_enabled;
+        self
+    }
+}
+
+pub(crate) fn write_json(file_ref: &Path, content: &Config) -> Result<(), GstError> {
+    let serialized = serde_json::to_string_pretty(&content)
+        .map_err(|e| GstError::ConfigWrite(file_ref.to_path_buf(), e.to_string()))?;
+    fs::write(file_ref, serialized).map_err(|e| GstError::ConfigWrite(file_ref.to_path_buf(), e.to_string()))
+}
+
+// An environment variable, which is set and not empty
+fn env_path(var: &str) -> Option<PathBuf> {
+    env::var_os(var).filter(|v| !v.is_empty()).map(PathBuf::from)
+}
+
+fn found_path_in_paths(paths: &[PathBuf], fp: &str) -> bool {
+    paths.iter().any(|i| i.to_str().unwrap() == fp)
 }
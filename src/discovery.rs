@@ -0,0 +1,323 @@
+use std::{
+    fs,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::cache;
+use crate::config::Config;
+use crate::error::GstError;
+use crate::exclude::ExcludePatterns;
+
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum NestingMode {
+    // Nothing below a repository is searched
+    StopAtRoot,
+    // The work tree of a repository is searched for further independent repositories,
+    // but never its `.git` directory
+    Nested,
+}
+
+#[derive(Clone)]
+pub struct Repository {
+    // As discovered, which might lead through symlinks
+    pub work_tree: PathBuf,
+    // Without any symlinks
+    pub canonical_work_tree: PathBuf,
+    // Where `.git` points to. For linked worktrees and submodules `.git` is a file
+    // containing `gitdir: <path>`.
+    pub git_dir: PathBuf,
+    // Objects, refs and the stash. Shared by all worktrees of a repository.
+    pub common_dir: PathBuf,
+    // Work tree of the main repository, if this is a linked worktree
+    pub worktree_of: Option<PathBuf>,
+    // The main repository was found as well, so this is rendered below it
+    pub nested: bool,
+    // Bare repositories and mirrors have no work tree. `work_tree` is the repository itself.
+    pub bare: bool,
+    // `None` waits until `git fetch` is done
+    pub fetch_timeout: Option<Duration>,
+}
+
+impl Repository {
+    pub fn from_dot_git(dot_git: &Path, fetch_timeout: Option<Duration>) -> Result<Repository, GstError> {
+        let work_tree = match dot_git.parent() {
+            Some(v) => v.to_path_buf(),
+            None => return Err(GstError::NotARepository(dot_git.to_path_buf())),
+        };
+
+        let git_dir = match dot_git.is_dir() {
+            true => dot_git.to_path_buf(),
+            false => {
+                let content = fs::read_to_string(dot_git).map_err(|e| GstError::Io(dot_git.to_path_buf(), e.to_string()))?;
+                let gitdir = content
+                    .lines()
+                    .find_map(|line| line.strip_prefix("gitdir:"))
+                    .ok_or_else(|| GstError::NotARepository(dot_git.to_path_buf()))?;
+                work_tree.join(gitdir.trim())
+            }
+        };
+        let git_dir = fs::canonicalize(&git_dir).unwrap_or(git_dir);
+
+        // Only linked worktrees have a `commondir` file, which points to the main git dir
+        let (common_dir, worktree_of) = match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(v) => {
+                let common_dir = git_dir.join(v.trim());
+                let common_dir = fs::canonicalize(&common_dir).unwrap_or(common_dir);
+                // A bare repository is its own main work tree
+                let worktree_of = match common_dir.ends_with(".git") {
+                    true => common_dir.parent().map(|v| v.to_path_buf()),
+                    false if is_bare_repository(&common_dir) => Some(common_dir.clone()),
+                    false => None,
+                };
+                (common_dir, worktree_of)
+            }
+            Err(_) => (git_dir.clone(), None),
+        };
+
+        Ok(Repository {
+            canonical_work_tree: fs::canonicalize(&work_tree).unwrap_or(work_tree.clone()),
+            work_tree,
+            git_dir,
+            common_dir,
+            worktree_of,
+            nested: false,
+            bare: false,
+            fetch_timeout,
+        })
+    }
+
+    pub fn from_bare(path: &Path, fetch_timeout: Option<Duration>) -> Repository {
+        let git_dir = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+        Repository {
+            work_tree: path.to_path_buf(),
+            canonical_work_tree: git_dir.clone(),
+            git_dir: git_dir.clone(),
+            common_dir: git_dir,
+            worktree_of: None,
+            nested: false,
+            bare: true,
+            fetch_timeout,
+        }
+    }
+
+    pub fn is_linked_worktree(&self) -> bool {
+        self.git_dir != self.common_dir
+    }
+
+    pub fn is_symlinked(&self) -> bool {
+        self.work_tree != self.canonical_work_tree
+    }
+}
+
+// Identifies a directory, no matter through which symlinks it was reached
+#[cfg(target_family = "unix")]
+type DirId = (u64, u64);
+
+#[cfg(target_family = "unix")]
+fn dir_id(path: &Path) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|v| (v.dev(), v.ino()))
+}
+
+#[cfg(not(target_family = "unix"))]
+type DirId = PathBuf;
+
+#[cfg(not(target_family = "unix"))]
+fn dir_id(path: &Path) -> Option<DirId> {
+    fs::canonicalize(path).ok()
+}
+
+// Walks a project path depth first, and yields every repository below it, in the order
+// they are found. Unreadable directories are yielded as errors, and the walk goes on.
+pub struct Discovery {
+    root: PathBuf,
+    excludes: ExcludePatterns,
+    nesting_mode: NestingMode,
+    follow_symlinks: bool,
+    max_depth: i8,
+    fetch_timeout: Option<Duration>,
+    started: bool,
+    // Directories from the project path down to the current one. Stops symlink cycles.
+    open_dirs: Vec<OpenDir>,
+    // Yielded right after a repository, which was found in the same directory
+    queued: Option<GstError>,
+    // Modification times of the searched directories
+    mtimes: BTreeMap<PathBuf, u64>,
+}
+
+struct OpenDir {
+    path: PathBuf,
+    entries: fs::ReadDir,
+    id: Option<DirId>,
+}
+
+impl Discovery {
+    // Takes the exclude patterns, the nesting mode, the search depth, etc. from `config`
+    pub fn new(root: &Path, config: &Config) -> Discovery {
+        Discovery {
+            root: root.to_path_buf(),
+            excludes: ExcludePatterns::load(&config.exclude, root),
+            nesting_mode: match config.nested_repositories {
+                true => NestingMode::Nested,
+                false => NestingMode::StopAtRoot,
+            },
+            follow_symlinks: config.follow_symlinks,
+            max_depth: config.search_depth,
+            fetch_timeout: match config.fetch_timeout_for(root) {
+                0 => None,
+                v => Some(Duration::from_secs(v)),
+            },
+            started: false,
+            open_dirs: vec![],
+            queued: None,
+            mtimes: BTreeMap::new(),
+        }
+    }
+
+    pub fn nesting_mode(&mut self, nesting_mode: NestingMode) -> &mut Self {
+        self.nesting_mode = nesting_mode;
+        self
+    }
+
+    // The project path itself is at depth 1
+    pub fn max_depth(&mut self, max_depth: i8) -> &mut Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Every directory searched so far, and its modification time in nanoseconds
+    pub fn into_mtimes(self) -> BTreeMap<PathBuf, u64> {
+        self.mtimes
+    }
+
+    // Checks a directory for `.git`, and opens it, so its entries are walked next
+    fn enter(&mut self, dir: PathBuf, id: Option<DirId>) -> Option<Result<Repository, GstError>> {
+        // Also of a missing project path, so a cache notices, once it's created
+        self.mtimes.insert(dir.clone(), cache::mtime(&dir));
+        if !dir.is_dir() {
+            return Some(Err(GstError::InvalidPath(dir)));
+        }
+
+        let depth = self.open_dirs.len() + 1;
+        if depth > usize::try_from(self.max_depth).unwrap_or(0) {
+            return None;
+        }
+
+        if is_bare_repository(&dir) {
+            // The object store of a mirror can be huge, and contains no further projects
+            return Some(Ok(Repository::from_bare(&dir, self.fetch_timeout)));
+        }
+
+        let dot_git = dir.join(".git");
+        let found = dot_git.exists().then(|| Repository::from_dot_git(&dot_git, self.fetch_timeout));
+        if found.is_some() && self.nesting_mode == NestingMode::StopAtRoot {
+            return found;
+        }
+
+        match fs::read_dir(&dir) {
+            Ok(entries) => self.open_dirs.push(OpenDir { path: dir, entries, id }),
+            Err(e) => {
+                let e = GstError::Io(dir, e.to_string());
+                match found {
+                    Some(_) => self.queued = Some(e),
+                    None => return Some(Err(e)),
+                }
+            }
+        }
+        found
+    }
+}
+
+impl Iterator for Discovery {
+    type Item = Result<Repository, GstError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.queued.take() {
+            return Some(Err(e));
+        }
+        if !self.started {
+            self.started = true;
+            let root = self.root.clone();
+            let id = dir_id(&root);
+            if let Some(found) = self.enter(root, id) {
+                return Some(found);
+            }
+        }
+
+        loop {
+            let dir = self.open_dirs.last_mut()?;
+            let entry = match dir.entries.next() {
+                Some(Ok(v)) => v,
+                Some(Err(e)) => {
+                    let path = dir.path.clone();
+                    self.open_dirs.pop();
+                    return Some(Err(GstError::Io(path, e.to_string())));
+                }
+                None => {
+                    self.open_dirs.pop();
+                    continue;
+                }
+            };
+            let path = entry.path();
+
+            // Checked, when its parent was entered. Its internals never contain further projects.
+            if path.ends_with(".git") {
+                continue;
+            }
+
+            let is_symlink = entry.file_type().map(|v| v.is_symlink()).unwrap_or(false);
+            if is_symlink && !self.follow_symlinks {
+                continue;
+            }
+
+            if path.is_dir() && self.excludes.is_excluded(&self.root, &path) {
+                continue;
+            }
+
+            if path.is_dir() {
+                // A symlink pointing to a parent directory would loop until the depth limit
+                let Some(id) = dir_id(&path) else {
+                    continue;
+                };
+                if self.open_dirs.iter().any(|v| v.id.as_ref() == Some(&id)) {
+                    continue;
+                }
+                if let Some(found) = self.enter(path, Some(id)) {
+                    return Some(found);
+                }
+            }
+        }
+    }
+}
+
+// Has the layout of a git dir (`HEAD`, `objects` and `refs`), and `core.bare` is set. The
+// latter rules out the git dirs of submodules below `.git/modules`.
+pub fn is_bare_repository(path: &Path) -> bool {
+    if !path.join("HEAD").is_file() || !path.join("objects").is_dir() || !path.join("refs").is_dir() {
+        return false;
+    }
+    let Ok(config) = fs::read_to_string(path.join("config")) else {
+        return false;
+    };
+
+    let mut section = String::new();
+    for line in config.lines().map(|v| v.trim()) {
+        if line.starts_with('[') {
+            section = line.to_lowercase();
+            continue;
+        }
+        if section != "[core]" {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim().eq_ignore_ascii_case("bare") {
+                return value.trim().eq_ignore_ascii_case("true");
+            }
+        }
+    }
+    false
+}
+
@@ -0,0 +1,110 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+
+// Exit codes, which scripts can rely on
+pub const EXIT_CLEAN: i32 = 0;
+// Only with `--exit-code`: at least one project has changes, or commits ahead/behind
+pub const EXIT_CHANGES: i32 = 1;
+pub const EXIT_ERROR: i32 = 2;
+// The configuration file, or an argument is invalid
+pub const EXIT_CONFIG_ERROR: i32 = 3;
+
+#[derive(Debug, Clone)]
+pub enum GstError {
+    // The environment variable of the home directory is not set
+    HomeNotSet(&'static str),
+    ConfigRead(PathBuf, String),
+    // The config file is empty, or no valid JSON
+    ConfigCorrupt(PathBuf),
+    // The config file is of an older major version, which cannot be migrated, or of an unknown one
+    VersionMismatch { path: PathBuf, config: String, current: String },
+    // The config file was written by a newer major version of this program
+    VersionNewer { path: PathBuf, config: String, current: String },
+    ConfigWrite(PathBuf, String),
+    // `git` cannot be executed
+    GitMissing,
+    // No project path is configured, and none was given
+    NoProjectPaths,
+    // A project path, which doesn't exist or is not a directory
+    InvalidPath(PathBuf),
+    // A `.git` file, which doesn't point to a git dir
+    NotARepository(PathBuf),
+    // Reading a directory or file failed
+    Io(PathBuf, String),
+    // The output of `git fetch`
+    CannotFetchRemote(String),
+    FetchTimedOut(Duration),
+    // The output of any other git command
+    Git(String),
+    // The projects cannot be watched for changes
+    Watch(String),
+}
+
+impl GstError {
+    // Configuration errors are fixed differently than errors of the projects, so
+    // they get their own exit code
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GstError::HomeNotSet(_)
+            | GstError::ConfigRead(..)
+            | GstError::ConfigCorrupt(_)
+            | GstError::VersionMismatch { .. }
+            | GstError::VersionNewer { .. }
+            | GstError::ConfigWrite(..)
+            | GstError::NoProjectPaths => EXIT_CONFIG_ERROR,
+            _ => EXIT_ERROR,
+        }
+    }
+
+    // The path the error refers to, if it's not a repository as a whole
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            GstError::InvalidPath(path) | GstError::NotARepository(path) | GstError::Io(path, _) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GstError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GstError::HomeNotSet(var) => write!(f, "Cannot find the home directory. `${}` is not set", var),
+            GstError::ConfigRead(path, e) => write!(f, "Cannot read the configuration file `{}`: {}",
+                                                     path.display(), e),
+            GstError::ConfigCorrupt(path) => write!(f, "Config file `{}` seems to be empty or corrupted. \
+                                                       Delete the file and run the program again.", path.display()),
+            GstError::VersionMismatch { path, config, .. } if config == "UNKNOWN" => {
+                write!(f, "The configuration file `{}` contains an unknown version. Consider to delete \
+                          the config and run this program again, to build a new one.", path.display())
+            }
+            GstError::VersionMismatch { path, config, current } => {
+                write!(f, "The configuration version {} in `{}` is no longer supported, and cannot be migrated \
+                          to {}. Remove the config file and run this program again, to build a new one.",
+                       config, path.display(), current)
+            }
+            GstError::VersionNewer { path, config, current } => {
+                write!(f, "The configuration version {} in `{}` is newer than this program ({}). Please update \
+                          this program, or remove the config file and run it again.", config, path.display(), current)
+            }
+            GstError::ConfigWrite(path, e) => write!(f, "Could not write `{}`: {}", path.display(), e),
+            GstError::GitMissing => write!(f, "Cannot find 'Git', make sure you have Git installed \
+                                              and/or Git in $PATH"),
+            GstError::NoProjectPaths => write!(f, "No paths configured. Provide project paths via \
+                                               `-p, --path <PATH>`\n or `--paths \"<PATH> <PATH> <PATH>\"`"),
+            GstError::InvalidPath(_) => write!(f, "Is not a valid path"),
+            GstError::NotARepository(_) => write!(f, "Is neither a `.git` directory, nor a `.git` file \
+                                                   with `gitdir: <path>`"),
+            GstError::Io(_, e) => write!(f, "{}", e),
+            GstError::CannotFetchRemote(e) => write!(f, "{}", e),
+            GstError::FetchTimedOut(timeout) => write!(f, "Fetch timed out after {}s", timeout.as_secs()),
+            GstError::Git(e) => write!(f, "{}", e),
+            GstError::Watch(e) => write!(f, "Cannot watch the projects: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GstError {}
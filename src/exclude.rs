@@ -0,0 +1,304 @@
+use std::{
+    fs,
+    path::Path,
+};
+
+
+// File in a scanned root, with one pattern per line
+pub const IGNORE_FILE: &str = ".gstignore";
+
+// A single gitignore-style pattern:
+//  `*` matches anything but `/`, `**` matches across directories, `?` a single character
+//  and `[a-z]` a character class. A leading `!` re-includes a directory, and a pattern
+//  containing a `/` is anchored to the scanned root. Otherwise the directory name is matched.
+struct Pattern {
+    glob: Vec<char>,
+    negated: bool,
+    anchored: bool,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Pattern> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(v) => (true, v),
+            None => (false, line),
+        };
+        // Only directories are matched, so a trailing `/` makes no difference
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Pattern {
+            glob: line.chars().collect(),
+            negated,
+            anchored,
+        })
+    }
+
+    fn matches(&self, relative_path: &[char], name: &[char]) -> bool {
+        match self.anchored {
+            true => glob_match(&self.glob, relative_path),
+            false => glob_match(&self.glob, name),
+        }
+    }
+}
+
+pub struct ExcludePatterns {
+    patterns: Vec<Pattern>,
+}
+
+impl ExcludePatterns {
+    // The patterns of the configuration, followed by the ones of `.gstignore` in `root`
+    pub fn load(config_patterns: &[String], root: &Path) -> ExcludePatterns {
+        let ignore_file = fs::read_to_string(root.join(IGNORE_FILE)).unwrap_or_default();
+        let patterns = config_patterns
+            .iter()
+            .map(|v| v.as_str())
+            .chain(ignore_file.lines())
+            .filter_map(Pattern::parse)
+            .collect();
+        ExcludePatterns { patterns }
+    }
+
+    // Like gitignore, the last matching pattern decides
+    pub fn is_excluded(&self, root: &Path, dir: &Path) -> bool {
+        let Ok(relative_path) = dir.strip_prefix(root) else {
+            return false;
+        };
+        let relative_path: Vec<char> = relative_path
+            .components()
+            .map(|v| v.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+            .chars()
+            .collect();
+        let name: Vec<char> = dir
+            .file_name()
+            .map(|v| v.to_string_lossy().chars().collect())
+            .unwrap_or_default();
+
+        let mut excluded = false;
+        for pattern in &self.patterns {
+            if pattern.matches(&relative_path, &name) {
+                excluded = !pattern.negated;
+            }
+        }
+        excluded
+    }
+}
+
+fn glob_match(glob: &[char], text: &[char]) -> bool {
+    match glob.first() {
+        None => text.is_empty(),
+        Some('*') if glob.get(1) == Some(&'*') => {
+            let rest = &glob[2..];
+            // `**/` also matches no directory at all
+            if rest.first() == Some(&'/') && glob_match(&rest[1..], text) {
+                return true;
+            }
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some('*') => {
+            let segment_len = text.iter().position(|v| *v == '/').unwrap_or(text.len());
+            (0..=segment_len).any(|i| glob_match(&glob[1..], &text[i..]))
+        }
+        Some('?') => match text.first() {
+            Some(c) if *c != '/' => glob_match(&glob[1..], &text[1..]),
+            _ => false,
+        },
+        Some('[') => match (text.first(), glob.iter().skip(2).position(|v| *v == ']')) {
+            (Some(c), Some(end)) if *c != '/' => {
+                let class = &glob[1..end + 2];
+                match_class(class, *c) && glob_match(&glob[end + 3..], &text[1..])
+            }
+            // Without a closing bracket, `[` is taken literally
+            (Some('['), None) => glob_match(&glob[1..], &text[1..]),
+            _ => false,
+        },
+        Some('\\') if glob.len() > 1 => match text.first() {
+            Some(c) if *c == glob[1] => glob_match(&glob[2..], &text[1..]),
+            _ => false,
+        },
+        Some(g) => match text.first() {
+            Some(c) if c == g => glob_match(&glob[1..], &text[1..]),
+            _ => false,
+        },
+    }
+}
+
+// `class` is the content between the brackets, e.g. `!a-z_`
+fn match_class(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.first() {
+        Some('!') | Some('^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut found = false;
+    let mut idx = 0;
+    while idx < class.len() {
+        if idx + 2 < class.len() && class[idx + 1] == '-' {
+            found |= class[idx] <= c && c <= class[idx + 2];
+            idx += 3;
+        } else {
+            found |= class[idx] == c;
+            idx += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, text: &str) -> bool {
+        let glob: Vec<char> = glob.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        glob_match(&glob, &text)
+    }
+
+    fn excludes(patterns: &[&str]) -> ExcludePatterns {
+        ExcludePatterns {
+            patterns: patterns.iter().filter_map(|v| Pattern::parse(v)).collect(),
+        }
+    }
+
+    fn is_excluded(patterns: &ExcludePatterns, relative_path: &str) -> bool {
+        let root = Path::new("/home/usr/projects");
+        patterns.is_excluded(root, &root.join(relative_path))
+    }
+
+    #[test]
+    fn parse_skips_empty_lines_and_comments() {
+        assert!(Pattern::parse("").is_none());
+        assert!(Pattern::parse("   ").is_none());
+        assert!(Pattern::parse("# node_modules").is_none());
+        assert!(Pattern::parse("/").is_none());
+        assert!(Pattern::parse("!").is_none());
+    }
+
+    #[test]
+    fn parse_anchors_patterns_with_a_slash() {
+        let name = Pattern::parse("node_modules").unwrap();
+        assert!(!name.anchored);
+
+        let leading = Pattern::parse("/vendor").unwrap();
+        assert!(leading.anchored);
+        assert_eq!(leading.glob, vec!['v', 'e', 'n', 'd', 'o', 'r']);
+
+        let inner = Pattern::parse("vendor/cache").unwrap();
+        assert!(inner.anchored);
+    }
+
+    #[test]
+    fn parse_ignores_a_trailing_slash() {
+        let pattern = Pattern::parse("build/").unwrap();
+        assert!(!pattern.anchored);
+        assert_eq!(pattern.glob, vec!['b', 'u', 'i', 'l', 'd']);
+    }
+
+    #[test]
+    fn parse_negation() {
+        let pattern = Pattern::parse("!keep").unwrap();
+        assert!(pattern.negated);
+        assert_eq!(pattern.glob, vec!['k', 'e', 'e', 'p']);
+        assert!(!Pattern::parse("keep").unwrap().negated);
+    }
+
+    #[test]
+    fn star_stays_within_a_directory() {
+        assert!(matches("*", "node_modules"));
+        assert!(matches("node_*", "node_modules"));
+        assert!(matches("*_modules", "node_modules"));
+        assert!(!matches("*", "a/b"));
+        assert!(!matches("vendor/*", "vendor/a/b"));
+        assert!(matches("vendor/*", "vendor/a"));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        assert!(matches("**/cache", "cache"));
+        assert!(matches("**/cache", "a/b/cache"));
+        assert!(matches("vendor/**", "vendor/a/b"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(!matches("a/**/b", "x/a/b"));
+    }
+
+    #[test]
+    fn question_mark_matches_a_single_character() {
+        assert!(matches("v?", "v1"));
+        assert!(!matches("v?", "v"));
+        assert!(!matches("v?", "v12"));
+        assert!(!matches("a?b", "a/b"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(matches("v[0-9]", "v7"));
+        assert!(!matches("v[0-9]", "vx"));
+        assert!(matches("[abc]", "b"));
+        assert!(matches("[!abc]", "d"));
+        assert!(matches("[^abc]", "d"));
+        assert!(!matches("[!abc]", "a"));
+        assert!(matches("[a-c_]", "_"));
+        assert!(!matches("a[/]b", "a/b"));
+    }
+
+    #[test]
+    fn unterminated_class_is_literal() {
+        assert!(matches("[abc", "[abc"));
+        assert!(!matches("[abc", "a"));
+        assert!(!matches("[abc", "b"));
+    }
+
+    #[test]
+    fn escaped_characters_are_literal() {
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(matches("\\[a]", "[a]"));
+        assert!(matches("a\\?", "a?"));
+        assert!(!matches("a\\?", "ab"));
+    }
+
+    #[test]
+    fn name_patterns_match_at_any_depth() {
+        let patterns = excludes(&["node_modules"]);
+        assert!(is_excluded(&patterns, "node_modules"));
+        assert!(is_excluded(&patterns, "web/app/node_modules"));
+        assert!(!is_excluded(&patterns, "web/node_modules_old"));
+    }
+
+    #[test]
+    fn anchored_patterns_match_from_the_root() {
+        let patterns = excludes(&["/vendor", "web/dist/"]);
+        assert!(is_excluded(&patterns, "vendor"));
+        assert!(!is_excluded(&patterns, "lib/vendor"));
+        assert!(is_excluded(&patterns, "web/dist"));
+        assert!(!is_excluded(&patterns, "app/web/dist"));
+    }
+
+    #[test]
+    fn negation_re_includes_and_the_last_match_wins() {
+        let patterns = excludes(&["vendor/*", "!vendor/own"]);
+        assert!(is_excluded(&patterns, "vendor/foreign"));
+        assert!(!is_excluded(&patterns, "vendor/own"));
+
+        let patterns = excludes(&["!vendor/own", "vendor/*"]);
+        assert!(is_excluded(&patterns, "vendor/own"));
+    }
+
+    #[test]
+    fn paths_outside_of_the_root_are_never_excluded() {
+        let patterns = excludes(&["**"]);
+        assert!(!patterns.is_excluded(Path::new("/home/usr/projects"), Path::new("/tmp/projects")));
+    }
+}
@@ -4,19 +4,72 @@ pub struct Indicators {
     pub ok: &'static str,
     pub ahead: &'static str,
     pub behind: &'static str,
-    pub files: &'static str,
+    // Format slots for the numeric variant, `{}` is replaced with the count
+    pub ahead_count: &'static str,
+    pub behind_count: &'static str,
+    pub detached: &'static str,
+    pub no_upstream: &'static str,
+    pub no_upstream_count: &'static str,
+    pub staged: &'static str,
+    pub modified: &'static str,
+    pub deleted: &'static str,
+    pub renamed: &'static str,
+    pub untracked: &'static str,
+    pub unmerged: &'static str,
+    pub branches: &'static str,
+    pub branches_count: &'static str,
+    pub bare: &'static str,
+    pub submodules: &'static str,
+    pub submodules_count: &'static str,
+    pub submodule_uninitialized: &'static str,
+    pub submodule_commit: &'static str,
+    pub stash: &'static str,
+    pub stash_count: &'static str,
+    pub merge: &'static str,
+    pub rebase: &'static str,
+    pub cherry_pick: &'static str,
+    pub revert: &'static str,
+    pub bisect: &'static str,
+    pub stale: &'static str,
+    pub timeout: &'static str,
     pub err: &'static str,
 }
 
 impl Indicators {
     pub fn new(mode: bool) -> Indicators {
-        let indicators = match mode {
+        match mode {
             true => {
                 Indicators {
                     ok: "+",
                     ahead: "->",
                     behind: "<-",
-                    files: "*",
+                    ahead_count: "->{}",
+                    behind_count: "<-{}",
+                    detached: "@",
+                    no_upstream: "^",
+                    no_upstream_count: "^{}",
+                    staged: "S",
+                    modified: "*",
+                    deleted: "D",
+                    renamed: "R",
+                    untracked: "?",
+                    unmerged: "U",
+                    branches: "&",
+                    branches_count: "&{}",
+                    bare: "B",
+                    submodules: "#",
+                    submodules_count: "#{}",
+                    submodule_uninitialized: "-",
+                    submodule_commit: "%",
+                    stash: "$",
+                    stash_count: "${}",
+                    merge: "m!",
+                    rebase: "r!",
+                    cherry_pick: "c!",
+                    revert: "v!",
+                    bisect: "b!",
+                    stale: "~",
+                    timeout: "t",
                     err: "x",
                 }
             }
@@ -25,11 +78,40 @@ impl Indicators {
                     ok: "✓",
                     ahead: "→",
                     behind: "←",
-                    files: "◎",
+                    ahead_count: "→{}",
+                    behind_count: "←{}",
+                    detached: "⊘",
+                    no_upstream: "⇡",
+                    no_upstream_count: "⇡{}",
+                    staged: "●",
+                    modified: "◎",
+                    deleted: "⊖",
+                    renamed: "⇄",
+                    untracked: "◌",
+                    unmerged: "‼",
+                    branches: "⎇",
+                    branches_count: "⎇{}",
+                    bare: "▣",
+                    submodules: "⊞",
+                    submodules_count: "⊞{}",
+                    submodule_uninitialized: "○",
+                    submodule_commit: "±",
+                    stash: "⚑",
+                    stash_count: "⚑{}",
+                    merge: "⋈",
+                    rebase: "↻",
+                    cherry_pick: "⤳",
+                    revert: "↶",
+                    bisect: "½",
+                    stale: "≈",
+                    timeout: "⧖",
                     err: "⨯",
                 }
             }
-        };
-        indicators
+        }
+    }
+
+    pub fn with_count(slot: &str, count: u32) -> String {
+        slot.replace("{}", &count.to_string())
     }
 }
@@ -0,0 +1,34 @@
+//! GST traverses through Git projects, and displays their current `git status`.
+//! Copyright (C) 2024  adam at p-trace.com  key.p-trace.com
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//!
+//! The library behind the `gst` command. `Discovery` finds the repositories below a
+//! project path, and `provider::git_status` checks each of them.
+
+pub mod config;
+pub mod discovery;
+pub mod error;
+pub mod indicators;
+pub mod migrate;
+pub mod provider;
+pub mod render;
+mod cache;
+mod colors;
+mod exclude;
+
+pub use config::Config;
+pub use discovery::{Discovery, NestingMode, Repository};
+pub use error::GstError;
+pub use provider::{git_fetch, git_status, BranchMode, RepoStatus};
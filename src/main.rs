@@ -13,54 +13,184 @@
 ///
 /// You should have received a copy of the GNU General Public License
 /// along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use std::process::{exit, Command};
+use std::{
+    path::{Path, PathBuf},
+    process::exit,
+    time::Duration,
+};
 use clap::Parser;
+use gst::{config, discovery, provider, render, GstError};
+use gst::error::EXIT_CONFIG_ERROR;
 
-mod config;
 mod argparse;
-mod colors;
-mod indicators;
-mod provider;
-mod render;
 
 
 const MAX_SEARCH_DEPTH: i8 = 30;
 const MIN_SEARCH_DEPTH: i8 = 1;
-const MAX_SEARCH_HEIGHT: i8 = 0;
+const MAX_JOBS: u8 = 64;
+const MIN_JOBS: u8 = 1;
 
 
-fn main() -> () {
-    check_git_client();
+fn main() {
+    let cli = parse_args();
+    if let Err(e) = run(&cli) {
+        // Quiet mode only signals the error via the exit code
+        if !cli.quiet {
+            eprintln!("{}", e);
+        }
+        exit(e.exit_code());
+    }
+}
 
-    let cli = argparse::Cli::parse();
+fn run(cli: &argparse::Cli) -> Result<(), GstError> {
+    provider::check_git_client()?;
 
-    let mut config = config::Config::new();
-    let mut state = ConfigStateHandler::new();
+    let config_fp = config_path(cli)?;
 
-    check_config_version(&config);
+    // Subcommands work on the config file itself, so it's not loaded yet
+    if let Some(argparse::Command::Config { action }) = &cli.command {
+        return config_command(cli, action, &config_fp);
+    }
+
+    let mut config = load_config(cli, &config_fp)?;
+    let mut state = ConfigStateHandler::new();
 
     // args, that are writing into the config
-    path(&cli, &mut config, &mut state);
-    paths(&cli, &mut config, &mut state);
-    remove_path(&cli, &mut config, &mut state);
-    purge_paths(&cli, &mut config, &mut state);
-    ascii_mode(&cli, &mut config, &mut state);
-    search_depth(&cli, &mut config, &mut state);
+    path(cli, &mut config, &mut state);
+    paths(cli, &mut config, &mut state);
+    remove_path(cli, &mut config, &mut state);
+    purge_paths(cli, &mut config, &mut state);
+    exclude(cli, &mut config, &mut state);
+    remove_exclude(cli, &mut config, &mut state);
+    nested_repos(cli, &mut config, &mut state);
+    follow_symlinks(cli, &mut config, &mut state);
+    ascii_mode(cli, &mut config, &mut state);
+    counts(cli, &mut config, &mut state);
+    file_counts(cli, &mut config, &mut state);
+    all_branches(cli, &mut config, &mut state);
+    fetch(cli, &mut config, &mut state);
+    fetch_timeout(cli, &mut config, &mut state);
+    path_fetch_timeout(cli, &mut config, &mut state);
+    watch_fetch_interval(cli, &mut config, &mut state);
+    search_depth(cli, &mut config, &mut state);
+    jobs(cli, &mut config, &mut state);
 
     // write config and exit with 0
     match state.state {
         ConfigState::ConfigChange => {
-            config.write_config();
+            config.write_config()?;
+            if !cli.quiet {
+                println!("[*] Updated configuration");
+            }
+            exit(0);
         }
         ConfigState::NoConfigChange => ()
     }
 
     // args, that won't modify the config
-    show_config(&cli, &mut config);  // exits with 0
-    let verbose_mode = verbose(&cli);
-    let execute_type = current_dir(&cli);
+    show_config(cli, &mut config);  // exits with 0
+    let verbose_mode = verbose(cli);
+    let execute_type = current_dir(cli);
+    let fetch_mode = fetch_mode(cli, &config);
+    let output_format = output_format(cli);
+    let branch_mode = branch_mode(cli, &config);
+    let nesting_mode = nesting_mode(&config);
+    let cache_mode = cache_mode(cli);
+
+    let mut check_git = provider::CheckGitProjects::init(&config, execute_type, fetch_mode, verbose_mode,
+                                                         output_format, MAX_SEARCH_DEPTH);
+    check_git
+        .branch_mode(branch_mode)
+        .nesting_mode(nesting_mode)
+        .cache_mode(cache_mode);
+
+    match watch(cli, output_format) {
+        true => check_git.watch(watch_fetch_interval_for(&config))?,
+        false => check_git.scan()?,
+    };
+
+    if cli.exit_code || cli.quiet {
+        exit(check_git.exit_code());
+    }
+    Ok(())
+}
+
+// Invalid arguments exit with `EXIT_CONFIG_ERROR`, instead of the usage code of clap, which
+// collides with `EXIT_ERROR`
+fn parse_args() -> argparse::Cli {
+    match argparse::Cli::try_parse() {
+        Ok(cli) => cli,
+        // `--help` and `--version`
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            let _ = e.print();
+            exit(EXIT_CONFIG_ERROR);
+        }
+    }
+}
+
+// Like `Config::open`, but tells about a created or migrated config file, unless it's quiet
+fn load_config(cli: &argparse::Cli, config_fp: &Path) -> Result<config::Config, GstError> {
+    if config::Config::create(config_fp)? && !cli.quiet {
+        eprintln!("'gst' config file does not exist. Created `{}`", config_fp.display());
+    }
+
+    let migration = config::Config::migration(config_fp)?;
+    if migration.is_needed() {
+        migration.apply()?;
+        if !cli.quiet {
+            eprintln!("Migrated the configuration from version {} to {}. The old one is kept in `{}`",
+                      migration.from, migration.to, migration.backup_path().display());
+        }
+    }
+    Ok(migration.config)
+}
+
+// Invalid values of arguments exit with `EXIT_CONFIG_ERROR`
+fn usage_error(cli: &argparse::Cli, msg: &str) -> ! {
+    if !cli.quiet {
+        eprintln!("{}", msg);
+    }
+    exit(EXIT_CONFIG_ERROR);
+}
+
+fn config_path(cli: &argparse::Cli) -> Result<PathBuf, GstError> {
+    match cli.config.as_deref() {
+        Some(config_fp) => Ok(PathBuf::from(normalize_path_str(config_fp))),
+        None => config::Config::default_path(),
+    }
+}
 
-    provider::CheckGitProjects::init(&config, execute_type, verbose_mode, MAX_SEARCH_DEPTH, MAX_SEARCH_HEIGHT).scan();
+fn config_command(cli: &argparse::Cli, action: &argparse::ConfigCommand,
+                  config_fp: &Path) -> Result<(), GstError> {
+    match action {
+        argparse::ConfigCommand::Migrate { dry_run } => migrate_config(cli, *dry_run, config_fp),
+    }
+}
+
+fn migrate_config(cli: &argparse::Cli, dry_run: bool, config_fp: &Path) -> Result<(), GstError> {
+    let mut migration = config::Config::migration(config_fp)?;
+    if cli.quiet && !dry_run {
+        if migration.is_needed() {
+            migration.apply()?;
+        }
+        return Ok(());
+    }
+
+    if !migration.is_needed() {
+        println!("[*] The configuration is up to date (version {})", migration.from);
+        return Ok(());
+    }
+
+    if dry_run {
+        eprintln!("The configuration would be migrated from version {} to {}:", migration.from, migration.to);
+        migration.config.show_config();
+        return Ok(());
+    }
+    migration.apply()?;
+    println!("[*] Migrated the configuration from version {} to {}. The old one is kept in `{}`",
+             migration.from, migration.to, migration.backup_path().display());
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -75,10 +205,9 @@ struct ConfigStateHandler {
 
 impl ConfigStateHandler {
     fn new() -> ConfigStateHandler {
-        let state = ConfigStateHandler {
+        ConfigStateHandler {
             state: ConfigState::NoConfigChange,
-        };
-        state
+        }
     }
 
     fn config_change(&mut self) -> &mut Self {
@@ -88,19 +217,6 @@ impl ConfigStateHandler {
 }
 
 
-fn normalize_path_string(path: &String) -> String {
-    #[cfg(target_family = "windows")]
-    let pattern = ("/", "\\");
-
-    #[cfg(target_family = "windows")]
-    let s = path.replace(pattern.0, pattern.1);
-
-    #[cfg(target_family = "unix")]
-    let s = path.clone();
-
-    s
-}
-
 fn normalize_path_str(path: &str) -> String {
     #[cfg(target_family = "windows")]
     let pattern = ("/", "\\");
@@ -115,17 +231,17 @@ fn normalize_path_str(path: &str) -> String {
 }
 
 fn path(cli: &argparse::Cli, config: &mut config::Config,
-        state_handler: &mut ConfigStateHandler) -> () {
+        state_handler: &mut ConfigStateHandler) {
     if let Some(paths) = cli.path.as_deref() {
         for path in paths {
-            config.add_path(&normalize_path_string(&path));
+            config.add_path(&normalize_path_str(path));
             state_handler.config_change();
         }
     }
 }
 
 fn paths(cli: &argparse::Cli, config: &mut config::Config,
-         state_handler: &mut ConfigStateHandler) -> () {
+         state_handler: &mut ConfigStateHandler) {
     if let Some(paths) = cli.paths.as_deref() {
         let paths_buf: Vec<&str> = paths.split(' ').collect();
         for path in paths_buf {
@@ -136,7 +252,7 @@ fn paths(cli: &argparse::Cli, config: &mut config::Config,
 }
 
 fn remove_path(cli: &argparse::Cli, config: &mut config::Config,
-               state_handler: &mut ConfigStateHandler) -> () {
+               state_handler: &mut ConfigStateHandler) {
     if let Some(remove_path) = cli.remove_path.as_deref() {
         config.remove_path(&normalize_path_str(remove_path));
         state_handler.config_change();
@@ -144,64 +260,165 @@ fn remove_path(cli: &argparse::Cli, config: &mut config::Config,
 }
 
 fn purge_paths(cli: &argparse::Cli, config: &mut config::Config,
-               state_handler: &mut ConfigStateHandler) -> () {
-    match cli.purge_paths {
-        true => {
-            config.purge_paths();
+               state_handler: &mut ConfigStateHandler) {
+    if cli.purge_paths {
+        config.purge_paths();
+        state_handler.config_change();
+    }
+}
+
+fn exclude(cli: &argparse::Cli, config: &mut config::Config,
+           state_handler: &mut ConfigStateHandler) {
+    if let Some(patterns) = cli.exclude.as_deref() {
+        for pattern in patterns {
+            config.add_exclude(pattern);
             state_handler.config_change();
         }
-        false => ()
     }
 }
 
-fn ascii_mode(cli: &argparse::Cli, config: &mut config::Config,
-              state_handler: &mut ConfigStateHandler) -> () {
-    match cli.ascii_mode.as_deref().unwrap_or("") {
-        s if s.to_lowercase() == "true" => {
-            config.ascii_enabled(true);
+fn remove_exclude(cli: &argparse::Cli, config: &mut config::Config,
+                  state_handler: &mut ConfigStateHandler) {
+    if let Some(pattern) = cli.remove_exclude.as_deref() {
+        config.remove_exclude(pattern);
+        state_handler.config_change();
+    }
+}
+
+// A setting, which is either `true` or `false`. Any other value is a usage error.
+fn parse_bool_setting(cli: &argparse::Cli, value: Option<&str>, config: &mut config::Config,
+                      setter: fn(&mut config::Config, bool) -> &mut config::Config,
+                      state_handler: &mut ConfigStateHandler) {
+    match value.map(|v| v.to_lowercase()).as_deref() {
+        Some("true") => {
+            setter(config, true);
             state_handler.config_change();
         }
-        s if s.to_lowercase() == "false" => {
-            config.ascii_enabled(false);
+        Some("false") => {
+            setter(config, false);
             state_handler.config_change();
-        },
-        s if !s.is_empty() => {
-            eprintln!("Unkown value. Try `true` or `false`");
-            exit(1);
         }
-        _ => ()
+        Some(_) => usage_error(cli, "Unknown value. Try `true` or `false`"),
+        None => (),
     }
 }
 
-fn search_depth(cli: &argparse::Cli, config: &mut config::Config,
-                state_handler: &mut ConfigStateHandler) -> () {
-    match cli.search_depth {
-        Some(v) => {
-            if (v < MIN_SEARCH_DEPTH) || (v > MAX_SEARCH_DEPTH) {
-                eprintln!("Provided search depth is out of bounds. \
-                          Please choose a number from {} to {}",
-                          MIN_SEARCH_DEPTH,
-                          MAX_SEARCH_DEPTH);
-                exit(1);
+fn nested_repos(cli: &argparse::Cli, config: &mut config::Config,
+                state_handler: &mut ConfigStateHandler) {
+    parse_bool_setting(cli, cli.nested_repos.as_deref(), config, config::Config::nested_repositories_enabled, state_handler);
+}
+
+fn follow_symlinks(cli: &argparse::Cli, config: &mut config::Config,
+                   state_handler: &mut ConfigStateHandler) {
+    parse_bool_setting(cli, cli.follow_symlinks.as_deref(), config, config::Config::follow_symlinks_enabled, state_handler);
+}
+
+fn ascii_mode(cli: &argparse::Cli, config: &mut config::Config,
+              state_handler: &mut ConfigStateHandler) {
+    parse_bool_setting(cli, cli.ascii_mode.as_deref(), config, config::Config::ascii_enabled, state_handler);
+}
+
+fn counts(cli: &argparse::Cli, config: &mut config::Config,
+          state_handler: &mut ConfigStateHandler) {
+    parse_bool_setting(cli, cli.counts.as_deref(), config, config::Config::counts_enabled, state_handler);
+}
+
+fn file_counts(cli: &argparse::Cli, config: &mut config::Config,
+               state_handler: &mut ConfigStateHandler) {
+    parse_bool_setting(cli, cli.file_counts.as_deref(), config, config::Config::file_counts_enabled, state_handler);
+}
+
+fn all_branches(cli: &argparse::Cli, config: &mut config::Config,
+                state_handler: &mut ConfigStateHandler) {
+    parse_bool_setting(cli, cli.all_branches.as_deref(), config, config::Config::all_branches_enabled, state_handler);
+}
+
+fn fetch(cli: &argparse::Cli, config: &mut config::Config,
+         state_handler: &mut ConfigStateHandler) {
+    parse_bool_setting(cli, cli.fetch.as_deref(), config, config::Config::fetch_enabled, state_handler);
+}
+
+fn fetch_timeout(cli: &argparse::Cli, config: &mut config::Config,
+                 state_handler: &mut ConfigStateHandler) {
+    if let Some(v) = cli.fetch_timeout {
+        config.fetch_timeout(v);
+        state_handler.config_change();
+    }
+}
+
+fn path_fetch_timeout(cli: &argparse::Cli, config: &mut config::Config,
+                      state_handler: &mut ConfigStateHandler) {
+    if let Some(v) = cli.path_fetch_timeout.as_deref() {
+        let seconds = v.rsplit_once('=').and_then(|(path, seconds)| {
+            Some((path, seconds.trim().parse::<u64>().ok()?))
+        });
+        match seconds {
+            Some((path, seconds)) if !path.is_empty() => {
+                // The timeout is looked up by project path, so it's stored as the path was configured
+                let path = normalize_path_str(path);
+                let project_path = config.project_paths
+                    .iter()
+                    .find(|v| v.as_path() == Path::new(&path))
+                    .map(|v| v.to_string_lossy().to_string());
+                match project_path {
+                    Some(v) => {
+                        config.path_fetch_timeout(&v, seconds);
+                        state_handler.config_change();
+                    }
+                    None => usage_error(cli, &format!("`{}` is not a configured project path. \
+                                                       Add it with `-p, --path <PATH>` first", path)),
+                }
+            }
+            _ => {
+                usage_error(cli, "Unknown value. Try `<PATH>=<SECONDS>`");
             }
-            config.search_depth(v);
-            state_handler.config_change();
         }
-        None => ()
     }
 }
 
-fn show_config(cli: &argparse::Cli, config: &mut config::Config) -> () {
-    match cli.show_config {
-        true => {
-            config.show_config();
-            exit(0);
+fn watch_fetch_interval(cli: &argparse::Cli, config: &mut config::Config,
+                        state_handler: &mut ConfigStateHandler) {
+    if let Some(v) = cli.watch_fetch_interval {
+        config.watch_fetch_interval(v);
+        state_handler.config_change();
+    }
+}
+
+fn search_depth(cli: &argparse::Cli, config: &mut config::Config,
+                state_handler: &mut ConfigStateHandler) {
+    if let Some(v) = cli.search_depth {
+        if !(MIN_SEARCH_DEPTH..=MAX_SEARCH_DEPTH).contains(&v) {
+            usage_error(cli, &format!("Provided search depth is out of bounds. \
+                                      Please choose a number from {} to {}", MIN_SEARCH_DEPTH, MAX_SEARCH_DEPTH));
+        }
+        config.search_depth(v);
+        state_handler.config_change();
+    }
+}
+
+fn jobs(cli: &argparse::Cli, config: &mut config::Config,
+        state_handler: &mut ConfigStateHandler) {
+    if let Some(v) = cli.jobs {
+        if !(MIN_JOBS..=MAX_JOBS).contains(&v) {
+            usage_error(cli, &format!("Provided number of jobs is out of bounds. \
+                                      Please choose a number from {} to {}", MIN_JOBS, MAX_JOBS));
         }
-        false => ()
+        config.jobs(v);
+        state_handler.config_change();
+    }
+}
+
+fn show_config(cli: &argparse::Cli, config: &mut config::Config) {
+    if cli.show_config {
+        config.show_config();
+        exit(0);
     }
 }
 
 fn verbose(cli: &argparse::Cli) -> render::VerboseMode {
+    if cli.quiet {
+        return render::VerboseMode::Quiet;
+    }
     match cli.verbose {
         1 => {
             render::VerboseMode::Verbose1
@@ -220,43 +437,61 @@ fn current_dir(cli: &argparse::Cli) -> provider::ExecuteType {
     }
 }
 
-fn check_git_client() -> () {
-    let git = Command::new("git")
-        .args(["--version"])
-        .output()
-        .unwrap();
+fn fetch_mode(cli: &argparse::Cli, config: &config::Config) -> provider::FetchMode {
+    if cli.no_fetch || !config.fetch {
+        return provider::FetchMode::Offline;
+    }
+    provider::FetchMode::Fetch
+}
 
-    let r = String::from_utf8(git.stdout).unwrap().to_lowercase();
+fn branch_mode(cli: &argparse::Cli, config: &config::Config) -> provider::BranchMode {
+    if cli.branches || config.all_branches {
+        return provider::BranchMode::All;
+    }
+    provider::BranchMode::Head
+}
 
-    if !r.contains("git") {
-        eprintln!("Cannot find 'Git', make sure you have Git installed \
-                 and/or Git in $PATH");
-        exit(1);
+fn nesting_mode(config: &config::Config) -> discovery::NestingMode {
+    match config.nested_repositories {
+        true => discovery::NestingMode::Nested,
+        false => discovery::NestingMode::StopAtRoot,
     }
 }
 
-fn check_config_version(config: &config::Config) -> () {
-    let current_version = option_env!("CARGO_PKG_VERSION").unwrap();
-    let keyword: &'static str = "UNKNOWN";
-    let version_from_config = &config.version;
+fn cache_mode(cli: &argparse::Cli) -> provider::CacheMode {
+    match cli.rescan {
+        true => provider::CacheMode::Rescan,
+        false => provider::CacheMode::Cached,
+    }
+}
 
-    if *version_from_config == keyword {
-        eprintln!("The configuration file in `$HOME/.config/gst/gst.json` contains an \
-                  unknown version. Consider to delete the config and run this program \
-                  again, to build a new one.");
-        exit(1);
+fn watch(cli: &argparse::Cli, output_format: render::OutputFormat) -> bool {
+    if cli.watch && output_format != render::OutputFormat::Text {
+        usage_error(cli, "Watch mode only supports the `text` format");
     }
+    if cli.watch && cli.quiet {
+        usage_error(cli, "Watch mode cannot be combined with `--quiet`");
+    }
+    cli.watch
+}
 
-    let current_version_coll: Vec<_> = current_version.split(".").collect();
-    let current_major = current_version_coll.first();
-    let config_version_coll: Vec<_> = version_from_config.split(".").collect();
-    let config_major = config_version_coll.first();
+fn watch_fetch_interval_for(config: &config::Config) -> Option<Duration> {
+    match config.watch_fetch_interval {
+        0 => None,
+        v => Some(Duration::from_secs(v)),
+    }
+}
 
-    if config_major == current_major {
-        return;
-    } else {
-        eprintln!("The configuration version in `$HOME/.config/gst/gst.json` diverged. \
-                  Please remove the config file, and run this programm again.");
-        exit(1);
+fn output_format(cli: &argparse::Cli) -> render::OutputFormat {
+    if cli.quiet && cli.format.is_some() {
+        usage_error(cli, "Quiet mode prints nothing. Remove `--format`");
+    }
+    match cli.format.as_deref().unwrap_or("text").to_lowercase().as_str() {
+        "text" => render::OutputFormat::Text,
+        "json" => render::OutputFormat::Json,
+        "ndjson" => render::OutputFormat::Ndjson,
+        _ => {
+            usage_error(cli, "Unknown value. Try `text`, `json` or `ndjson`");
+        }
     }
 }
@@ -0,0 +1,189 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{Map, Value};
+
+use crate::config::{self, Config};
+use crate::error::GstError;
+
+
+type MigrationFn = fn(&mut Map<String, Value>);
+
+// One migration per major version, from the version to the next one. `MIGRATIONS[0]`
+// migrates version 1 to 2, and so on. They are applied in order, until the current version
+// is reached.
+const MIGRATIONS: &[MigrationFn] = &[];
+
+// The content of a config file, migrated to the current version
+pub struct Migration {
+    pub path: PathBuf,
+    // The version in the file, and the one of this program
+    pub from: String,
+    pub to: String,
+    pub config: Config,
+    // Where the old file is kept. Next to the file, unless it's set otherwise.
+    pub backup_dir: PathBuf,
+}
+
+impl Migration {
+    // Minor and patch versions never change the config file
+    pub fn is_needed(&self) -> bool {
+        major(&self.from) != major(&self.to)
+    }
+
+    // The old file is kept in `backup_dir`, e.g. as `gst.json.v1.bak`
+    pub fn backup_path(&self) -> PathBuf {
+        let major = major(&self.from).unwrap_or(0);
+        let file_name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        self.backup_dir.join(format!("{}.v{}.bak", file_name, major))
+    }
+
+    // Backs up the old file, and writes the migrated config
+    pub fn apply(&self) -> Result<&Self, GstError> {
+        let backup_path = self.backup_path();
+        fs::create_dir_all(&self.backup_dir).map_err(|e| GstError::ConfigWrite(self.backup_dir.clone(), e.to_string()))?;
+        fs::copy(&self.path, &backup_path).map_err(|e| GstError::ConfigWrite(backup_path, e.to_string()))?;
+        config::write_json(&self.path, &self.config)?;
+        Ok(self)
+    }
+}
+
+pub fn migrate(path: &Path, content: &str) -> Result<Migration, GstError> {
+    migrate_to(path, content, env!("CARGO_PKG_VERSION"), MIGRATIONS)
+}
+
+fn migrate_to(path: &Path, content: &str, current: &str, migrations: &[MigrationFn]) -> Result<Migration, GstError> {
+    let mut value: Value = serde_json::from_str(content).map_err(|_| GstError::ConfigCorrupt(path.to_path_buf()))?;
+    let Some(fields) = value.as_object_mut() else {
+        return Err(GstError::ConfigCorrupt(path.to_path_buf()));
+    };
+
+    let current = current.to_string();
+    let from = fields
+        .get("version")
+        .and_then(|v| v.as_str())
+        .unwrap_or("UNKNOWN")
+        .to_string();
+    let version_mismatch = GstError::VersionMismatch {
+        path: path.to_path_buf(),
+        config: from.clone(),
+        current: current.clone(),
+    };
+
+    // Versions before 1, or newer than this program cannot be migrated
+    let (Some(from_major), Some(current_major)) = (major(&from), major(&current)) else {
+        return Err(version_mismatch);
+    };
+    if from_major == 0 {
+        return Err(version_mismatch);
+    }
+    if from_major > current_major {
+        return Err(GstError::VersionNewer { path: path.to_path_buf(), config: from, current });
+    }
+
+    if from_major < current_major {
+        let Some(migrations) = migrations.get(from_major - 1..current_major - 1) else {
+            return Err(version_mismatch);
+        };
+        for migration in migrations {
+            migration(fields);
+        }
+        fields.insert("version".to_string(), Value::String(current.clone()));
+    }
+
+    let mut config: Config = serde_json::from_value(value).map_err(|_| GstError::ConfigCorrupt(path.to_path_buf()))?;
+    config.path = path.to_path_buf();
+    Ok(Migration {
+        path: path.to_path_buf(),
+        from,
+        to: current,
+        config,
+        backup_dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+    })
+}
+
+fn major(version: &str) -> Option<usize> {
+    version.split('.').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "/home/usr/.config/gst/gst.json";
+
+    fn config_json(version: Option<&str>) -> String {
+        let version = version.map(|v| format!("\"version\": \"{}\",", v)).unwrap_or_default();
+        format!("{{ {} \"search_depth\": 5, \"ascii_mode\": false, \"project_paths\": [] }}", version)
+    }
+
+    fn rename_ascii_mode(config: &mut Map<String, Value>) {
+        if let Some(v) = config.remove("ascii") {
+            config.insert("ascii_mode".to_string(), v);
+        }
+    }
+
+    #[test]
+    fn same_major_version_is_not_migrated() {
+        let migration = migrate_to(Path::new(PATH), &config_json(Some("1.0.0")), "1.2.3", &[]).unwrap();
+        assert!(!migration.is_needed());
+        assert_eq!(migration.from, "1.0.0");
+        assert_eq!(migration.config.version, "1.0.0");
+        assert_eq!(migration.config.path(), Path::new(PATH));
+    }
+
+    #[test]
+    fn older_major_version_is_migrated() {
+        let content = r#"{ "version": "1.4.0", "search_depth": 5, "ascii": true, "project_paths": [] }"#;
+        let migration = migrate_to(Path::new(PATH), content, "2.0.0", &[rename_ascii_mode]).unwrap();
+        assert!(migration.is_needed());
+        assert_eq!(migration.from, "1.4.0");
+        assert_eq!(migration.to, "2.0.0");
+        assert_eq!(migration.config.version, "2.0.0");
+        assert!(migration.config.ascii_mode);
+    }
+
+    #[test]
+    fn older_major_version_without_migration_is_rejected() {
+        let result = migrate_to(Path::new(PATH), &config_json(Some("1.0.0")), "2.0.0", &[]);
+        assert!(matches!(result, Err(GstError::VersionMismatch { .. })));
+    }
+
+    #[test]
+    fn newer_major_version_is_rejected() {
+        let result = migrate_to(Path::new(PATH), &config_json(Some("3.1.0")), "2.0.0", &[rename_ascii_mode]);
+        assert!(matches!(result, Err(GstError::VersionNewer { config, .. }) if config == "3.1.0"));
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        for version in ["0.9.0", "latest", ""] {
+            let result = migrate_to(Path::new(PATH), &config_json(Some(version)), "2.0.0", &[rename_ascii_mode]);
+            assert!(matches!(result, Err(GstError::VersionMismatch { .. })), "version {:?}", version);
+        }
+    }
+
+    #[test]
+    fn missing_version_is_rejected() {
+        let result = migrate_to(Path::new(PATH), &config_json(None), "2.0.0", &[rename_ascii_mode]);
+        assert!(matches!(result, Err(GstError::VersionMismatch { config, .. }) if config == "UNKNOWN"));
+    }
+
+    #[test]
+    fn invalid_json_is_corrupt() {
+        let result = migrate_to(Path::new(PATH), "[1, 2]", "2.0.0", &[]);
+        assert!(matches!(result, Err(GstError::ConfigCorrupt(_))));
+    }
+
+    #[test]
+    fn backup_path_contains_the_old_major_version() {
+        let content = r#"{ "version": "1.4.0", "search_depth": 5, "ascii": true, "project_paths": [] }"#;
+        let mut migration = migrate_to(Path::new(PATH), content, "2.0.0", &[rename_ascii_mode]).unwrap();
+        assert_eq!(migration.backup_path(), Path::new("/home/usr/.config/gst/gst.json.v1.bak"));
+
+        migration.backup_dir = PathBuf::from("/tmp/gst");
+        assert_eq!(migration.backup_path(), Path::new("/tmp/gst/gst.json.v1.bak"));
+    }
+}
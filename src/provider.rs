@@ -1,7 +1,22 @@
-use std::{env, fs, path::PathBuf, process::{exit, Command}};
+use std::{
+    env, fs, thread,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Child, Command, Output, Stdio},
+    sync::{mpsc, atomic::{AtomicUsize, Ordering}},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+use notify::{event::ModifyKind, EventKind, RecursiveMode, Watcher};
+use serde::Serialize;
+
 use crate::indicators::Indicators;
 use crate::config::Config;
-use crate::render::{VerboseMode, TerminalDisplay};
+use crate::cache::{self, CachedProject, CachedRepository, DiscoveryCache};
+use crate::discovery::{Discovery, NestingMode, Repository};
+use crate::error::{self, GstError};
+use crate::exclude::{self, ExcludePatterns};
+use crate::render::{VerboseMode, OutputFormat, TerminalDisplay, JsonDisplay};
 
 
 pub enum ExecuteType {
@@ -9,206 +24,1605 @@ pub enum ExecuteType {
     CurrentPath
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum FetchMode {
+    Fetch,
+    // Skips `git fetch`, and compares against the last known remote-tracking refs
+    Offline,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum CacheMode {
+    // Reuses the repositories of the last run, unless a searched directory changed
+    Cached,
+    // Searches all project paths again, and updates the cache
+    Rescan,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum BranchMode {
+    // Only the checked out branch, like `git status`
+    Head,
+    // Every local branch, and its upstream
+    All,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum PathState {
+    NoRepository,
+    Found,
+    // Doesn't exist, or is not a directory
+    Invalid,
+}
+
 pub struct CheckGitProjects<'a> {
     pub config: &'a Config,
     pub execute_type: ExecuteType,
+    pub fetch_mode: FetchMode,
+    pub branch_mode: BranchMode,
+    pub nesting_mode: NestingMode,
+    pub cache_mode: CacheMode,
+    pub output_format: OutputFormat,
     pub indicators: Indicators,
     pub terminal_display: TerminalDisplay,
-    pub project_state: InformationHandler,
-    git_dir_state: Information,
-    rec_counter: i8,
+    pub json_display: JsonDisplay,
+    pub summary: Summary,
+    repositories: Vec<Repository>,
+    fetch_timeout: Option<Duration>,
+    cache: DiscoveryCache,
+    // Whether the current project path contains any repository
+    path_state: PathState,
     max_depth: i8,
-    max_height: i8,
 }
 
 impl <'a>CheckGitProjects<'a> {
-    pub fn init(config: &Config, execute_type: ExecuteType, verbose_mode: VerboseMode, max_search_depth: i8,
-                max_search_height: i8) -> CheckGitProjects {
-        let check_git = CheckGitProjects {
-            config: &config,
+    pub fn init(config: &Config, execute_type: ExecuteType, fetch_mode: FetchMode, verbose_mode: VerboseMode,
+                output_format: OutputFormat, max_search_depth: i8) -> CheckGitProjects<'_> {
+        CheckGitProjects {
+            config,
             execute_type,
+            fetch_mode,
+            branch_mode: BranchMode::Head,
+            nesting_mode: NestingMode::StopAtRoot,
+            cache_mode: CacheMode::Cached,
+            output_format,
             indicators: Indicators::new(config.ascii_mode),
-            terminal_display: TerminalDisplay::new(verbose_mode),
-            project_state: InformationHandler::new(),
-            git_dir_state: Information::NotFoundGitDir,
-            rec_counter: 1,
-
+            terminal_display: TerminalDisplay::new(verbose_mode, fetch_mode == FetchMode::Offline,
+                                                   config.show_counts, config.show_file_counts),
+            json_display: JsonDisplay::new(output_format),
+            summary: Summary::default(),
+            repositories: vec![],
+            fetch_timeout: None,
+            cache: DiscoveryCache::default(),
+            path_state: PathState::NoRepository,
             max_depth: max_search_depth,
-            max_height: max_search_height,
-        };
-        check_git
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.summary.exit_code()
+    }
+
+    pub fn branch_mode(&mut self, branch_mode: BranchMode) -> &mut Self {
+        self.branch_mode = branch_mode;
+        self
+    }
+
+    pub fn nesting_mode(&mut self, nesting_mode: NestingMode) -> &mut Self {
+        self.nesting_mode = nesting_mode;
+        self
+    }
+
+    pub fn cache_mode(&mut self, cache_mode: CacheMode) -> &mut Self {
+        self.cache_mode = cache_mode;
+        self
+    }
+
+    pub fn scan(&mut self) -> Result<&Self, GstError> {
+        self.discover()?;
+        self.collect_status();
+        self.json_display.finish();
+        self.render_summary();
+        Ok(self)
+    }
+
+    // Keeps running, and checks a project again, as soon as something changes in its work
+    // tree or git dir. The remotes are fetched in the background every `fetch_interval`.
+    pub fn watch(&mut self, fetch_interval: Option<Duration>) -> Result<&Self, GstError> {
+        self.discover()?;
+        // Invalid paths etc. are kept for every redraw
+        let discovered = self.summary;
+        let groups = self.group_repositories();
+        let repositories = std::mem::take(&mut self.repositories);
+        let mut results: Vec<Option<Result<RepoStatus, GstError>>> = vec![None; repositories.len()];
+        check_repositories(&repositories, &groups, self.config.jobs, self.fetch_mode, self.branch_mode,
+                           |idx, result| results[idx] = Some(result));
+        let mut last_fetch = (self.fetch_mode == FetchMode::Fetch).then(Instant::now);
+
+        // A failed fetch is shown instead of the status, until the next fetch succeeds
+        let mut fetch_errors: Vec<Option<GstError>> = results
+            .iter()
+            .map(|v| match v {
+                Some(Err(e @ (GstError::CannotFetchRemote(_) | GstError::FetchTimedOut(_)))) => Some(e.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let (sender, receiver) = mpsc::channel();
+        let watch_sender = sender.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            // Reading files, e.g. by `git status`, must not trigger another check
+            match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_))) => {
+                    let _ = watch_sender.send(WatchEvent::Changed(event.paths));
+                }
+                _ => (),
+            }
+        }).map_err(|e| GstError::Watch(e.to_string()))?;
+        let mut watched = BTreeSet::new();
+        for (path, mode) in watch_paths(&repositories, &self.config.exclude) {
+            if let Err(e) = watcher.watch(&path, mode) {
+                self.terminal_display.render_err(&e.to_string(), Some(&self.indicators), Some(&path));
+            }
+            watched.insert(path);
+        }
+
+        if let (FetchMode::Fetch, Some(interval)) = (self.fetch_mode, fetch_interval) {
+            let main_repositories: Vec<Repository> = groups.iter().map(|v| repositories[v[0]].clone()).collect();
+            thread::spawn(move || loop {
+                thread::sleep(interval);
+                // One remote after the other, to go easy on them
+                for (group_idx, repository) in main_repositories.iter().enumerate() {
+                    if sender.send(WatchEvent::Fetched(group_idx, git_fetch(repository))).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+
+        self.redraw(&repositories, &results, &fetch_errors, last_fetch, discovered);
+        while let Ok(event) = receiver.recv() {
+            // Changes come in bursts, e.g. by a checkout, and are handled at once
+            let deadline = Instant::now() + WATCH_DEBOUNCE;
+            let mut changed = BTreeSet::new();
+            let mut event = Some(event);
+            while let Some(v) = event.take() {
+                match v {
+                    WatchEvent::Changed(paths) => {
+                        // Work trees are watched directory by directory, so new ones are added
+                        for path in new_work_tree_dirs(&self.config.exclude, &watched, &paths) {
+                            if watcher.watch(&path, RecursiveMode::NonRecursive).is_ok() {
+                                watched.insert(path);
+                            }
+                        }
+                        changed.extend(affected_repositories(&repositories, &paths));
+                    }
+                    WatchEvent::Fetched(group_idx, result) => {
+                        last_fetch = Some(Instant::now());
+                        for idx in &groups[group_idx] {
+                            fetch_errors[*idx] = result.clone().err();
+                            changed.insert(*idx);
+                        }
+                    }
+                }
+                event = receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok();
+            }
+            if changed.is_empty() {
+                continue;
+            }
+
+            let changed: Vec<Vec<usize>> = changed.into_iter().map(|v| vec![v]).collect();
+            check_repositories(&repositories, &changed, self.config.jobs, FetchMode::Offline, self.branch_mode,
+                               |idx, result| results[idx] = Some(result));
+            self.redraw(&repositories, &results, &fetch_errors, last_fetch, discovered);
+        }
+        Ok(self)
+    }
+
+    fn redraw(&mut self, repositories: &[Repository], results: &[Option<Result<RepoStatus, GstError>>],
+              fetch_errors: &[Option<GstError>], last_fetch: Option<Instant>, discovered: Summary) {
+        self.terminal_display.clear_screen();
+        self.summary = discovered;
+        for (idx, repository) in repositories.iter().enumerate() {
+            let result = match (&fetch_errors[idx], &results[idx]) {
+                (Some(e), _) => Err(e.clone()),
+                (None, Some(v)) => v.clone(),
+                (None, None) => continue,
+            };
+            self.render_status(repository, result);
+        }
+        self.render_summary();
+        self.terminal_display.render_watch_footer(repositories.len(), last_fetch.map(|v| v.elapsed()));
     }
 
-    pub fn scan(&mut self) -> &Self {
+    // Collects all repositories below the configured project paths
+    fn discover(&mut self) -> Result<(), GstError> {
         let projects: &Vec<PathBuf>  = match self.execute_type {
             ExecuteType::FromConfig => {
                 &self.config.project_paths
             }
             ExecuteType::CurrentPath => {
-                &vec![env::current_dir().unwrap()]
+                let current_dir = env::current_dir().map_err(|e| GstError::Io(PathBuf::from("."), e.to_string()))?;
+                &vec![current_dir]
             }
         };
 
         if projects.is_empty() {
-            eprintln!("No paths configured. Provide project paths via `-p, --path <PATH>`\n \
-                      or `--paths \"<PATH> <PATH> <PATH>\"`");
-            exit(1);
+            return Err(GstError::NoProjectPaths);
         }
 
+        // Only the configured project paths are cached, not the ones of `--current-dir`. Without
+        // a home directory, there's no place for the cache.
+        let cache_path = Config::cache_path().ok().filter(|_| matches!(self.execute_type, ExecuteType::FromConfig));
+        let use_cache = cache_path.is_some();
+        if let Some(cache_path) = cache_path {
+            self.cache = DiscoveryCache::load(cache_path);
+        }
+        let settings = self.discovery_settings();
+
         for project in projects {
-            self.rec_counter = 1;
-            self.git_dir_state = Information::NotFoundGitDir;
-            self.traversal(&project);
+            self.path_state = PathState::NoRepository;
+            self.fetch_timeout = match self.config.fetch_timeout_for(project) {
+                0 => None,
+                v => Some(Duration::from_secs(v)),
+            };
+
+            let mut cached = match use_cache {
+                true => self.cache.get(project).cloned(),
+                false => None,
+            };
+            // Repositories, which vanished since the last run, are reported once
+            if let Some(cached) = &mut cached {
+                for repository in cached.repositories.iter().filter(|v| !v.exists()) {
+                    let msg = "Is no longer a Git project. Removed from the discovery cache";
+                    self.terminal_display.render_err(msg, Some(&self.indicators), Some(&repository.work_tree));
+                }
+                cached.repositories.retain(|v| v.exists());
+            }
+
+            let discovered = self.repositories.len();
+            match cached {
+                Some(cached) if self.cache_mode == CacheMode::Cached && cached.is_valid(&settings) => {
+                    match self.restore(&cached) {
+                        true => self.cache.remove(project),
+                        false => self.cache.insert(project, cached),
+                    };
+                }
+                _ => {
+                    let mut discovery = Discovery::new(project, self.config);
+                    discovery.nesting_mode(self.nesting_mode).max_depth(self.max_depth);
+                    let mut has_errors = false;
+                    for found in discovery.by_ref() {
+                        has_errors |= found.is_err();
+                        self.add_discovered(found);
+                    }
 
-            match self.git_dir_state {
-                Information::NotFoundGitDir => {
+                    // Errors are reported on every run, so their project path is searched again
+                    if use_cache && has_errors {
+                        self.cache.remove(project);
+                    } else if use_cache {
+                        let mut mtimes = discovery.into_mtimes();
+                        let ignore_file = project.join(exclude::IGNORE_FILE);
+                        mtimes.insert(ignore_file.clone(), cache::mtime(&ignore_file));
+                        let cached = CachedProject {
+                            settings: settings.clone(),
+                            mtimes,
+                            repositories: self.repositories[discovered..]
+                                .iter()
+                                .map(CachedRepository::from_repository)
+                                .collect(),
+                        };
+                        self.cache.insert(project, cached);
+                    }
+                }
+            }
+
+            match self.path_state {
+                PathState::NoRepository => {
+                    self.summary.invalid_paths += 1;
                     let msg_cannot_find_git_dirs: &'static str = "Cannot find any `.git` directory";
-                    self.terminal_display.render_path_err(&msg_cannot_find_git_dirs,
-                                                     &self.indicators, &project)
+                    self.terminal_display.render_path_err(msg_cannot_find_git_dirs,
+                                                     &self.indicators, project)
                 }
-                Information::NotValidPath => {
+                PathState::Invalid => {
+                    self.summary.invalid_paths += 1;
                     let msg_no_dir: &'static str = "Is not a valid path";
-                    self.terminal_display.render_path_err(&msg_no_dir.to_string(), &self.indicators, &project);
+                    self.terminal_display.render_path_err(msg_no_dir, &self.indicators, project);
                 }
-                _ => ()
+                PathState::Found => ()
             }
         }
 
-        match self.git_dir_state {
-            Information::NotFoundGitDir => {
-                return self;
+        if use_cache {
+            self.cache.retain(projects);
+            if let Err(e) = self.cache.write() {
+                let msg = format!("Could not write the discovery cache: {}", e);
+                self.terminal_display.render_err(&msg, None, None);
             }
-            Information::NotValidPath => {
-                return self;
+        }
+        Ok(())
+    }
+
+    fn render_summary(&self) {
+        if self.summary.is_all_clean() {
+            let msg_projects_ok: &'static str = "All projects are up to date";
+            self.terminal_display.render_ok_msg(msg_projects_ok, &self.indicators)
+        }
+
+        if self.fetch_mode == FetchMode::Offline {
+            let msg_offline: &'static str = "Remotes were not fetched. Ahead/behind may be outdated";
+            self.terminal_display.render_stale_msg(msg_offline, &self.indicators);
+        }
+        self.terminal_display.render_summary(&self.summary);
+    }
+
+    // Everything, that changes which repositories are found below a project path
+    fn discovery_settings(&self) -> String {
+        format!("exclude={:?} nested={} follow_symlinks={} max_depth={}",
+                self.config.exclude,
+                self.nesting_mode == NestingMode::Nested,
+                self.config.follow_symlinks,
+                self.max_depth)
+    }
+
+    // Returns, whether a cached repository cannot be opened anymore
+    fn restore(&mut self, cached: &CachedProject) -> bool {
+        let mut has_errors = false;
+        for repository in &cached.repositories {
+            let restored = match repository.bare {
+                true => Ok(Repository::from_bare(&repository.work_tree, self.fetch_timeout)),
+                false => Repository::from_dot_git(&repository.git_path(), self.fetch_timeout),
+            };
+            has_errors |= restored.is_err();
+            self.add_discovered(restored);
+        }
+        has_errors
+    }
+
+    fn add_discovered(&mut self, found: Result<Repository, GstError>) {
+        match found {
+            Ok(v) => {
+                self.repositories.push(v);
+                self.path_state = PathState::Found;
+            }
+            Err(GstError::InvalidPath(_)) => self.path_state = PathState::Invalid,
+            Err(e) => {
+                self.summary.errored += 1;
+                self.terminal_display.render_err(&e.to_string(), Some(&self.indicators), e.path());
+                // A broken `.git` is still a project
+                if e.path().is_some_and(|v| v.ends_with(".git")) {
+                    self.path_state = PathState::Found;
+                }
             }
-            _ => ()
         }
+    }
+
+    // Groups all worktrees, which share the same object store. The main repository comes
+    // first, followed by its linked worktrees. Otherwise the order of discovery is kept.
+    fn group_repositories(&mut self) -> Vec<Vec<usize>> {
+        let mut repositories: Vec<Repository> = vec![];
+        let mut groups: Vec<Vec<Repository>> = vec![];
+        let mut group_idx: HashMap<PathBuf, usize> = HashMap::new();
 
-        match self.project_state.info_state {
-            Information::AllGreen => {
-                let msg_projects_ok: &'static str = "All projects are up to date";
-                self.terminal_display.render_ok_msg(&msg_projects_ok, &self.indicators)
+        // Symlinks can lead to the same repository more than once. It's reported once,
+        // preferably by the path without symlinks.
+        let mut unique: Vec<Repository> = vec![];
+        let mut unique_idx: HashMap<PathBuf, usize> = HashMap::new();
+        for repository in std::mem::take(&mut self.repositories) {
+            match unique_idx.get(&repository.git_dir) {
+                Some(idx) if unique[*idx].is_symlinked() && !repository.is_symlinked() => {
+                    unique[*idx] = repository;
+                }
+                Some(_) => (),
+                None => {
+                    unique_idx.insert(repository.git_dir.clone(), unique.len());
+                    unique.push(repository);
+                }
             }
-            Information::CannotFetchRemote => (),
-            _ => ()
         }
-        self
+
+        // Submodules are reported as part of their superproject
+        let submodules: HashSet<PathBuf> = unique
+            .iter()
+            .flat_map(|v| {
+                submodule_paths(&v.work_tree).into_iter().map(|path| v.canonical_work_tree.join(path))
+            })
+            .collect();
+
+        for repository in unique {
+            if submodules.contains(&repository.canonical_work_tree) {
+                continue;
+            }
+            match group_idx.get(&repository.common_dir) {
+                Some(idx) => groups[*idx].push(repository),
+                None => {
+                    group_idx.insert(repository.common_dir.clone(), groups.len());
+                    groups.push(vec![repository]);
+                }
+            }
+        }
+
+        let mut indices = vec![];
+        for mut group in groups {
+            group.sort_by_key(|v| v.is_linked_worktree());
+            let has_main = !group[0].is_linked_worktree();
+
+            let mut group_indices = vec![];
+            for mut repository in group {
+                repository.nested = has_main && repository.is_linked_worktree();
+                group_indices.push(repositories.len());
+                repositories.push(repository);
+            }
+            indices.push(group_indices);
+        }
+
+        self.repositories = repositories;
+        indices
     }
 
-    // path counts from 0
-    // Depth is inclusive
-    fn traversal(&mut self, path: &PathBuf) -> () {
-        if !path.is_dir() {
-            self.git_dir_state = Information::NotValidPath;
+    fn collect_status(&mut self) {
+        let groups = self.group_repositories();
+        let repositories = std::mem::take(&mut self.repositories);
+        check_repositories(&repositories, &groups, self.config.jobs, self.fetch_mode, self.branch_mode,
+                           |idx, result| self.render_status(&repositories[idx], result));
+    }
+
+    fn render_status(&mut self, repository: &Repository, result: Result<RepoStatus, GstError>) {
+        self.summary.add(&result);
+        if self.output_format != OutputFormat::Text {
+            self.json_display.render_report(RepoReport::new(repository, result, self.fetch_mode));
+            return;
+        }
+        if self.terminal_display.verbose_mode == VerboseMode::Quiet {
             return;
         }
 
-        let dir_content = match fs::read_dir(path) {
-            Ok(v) => v,
+        let path = &repository.work_tree;
+        match result {
+            Ok(status) if repository.bare => {
+                self.terminal_display.render_bare(&status, repository, &self.indicators);
+            }
+            Ok(status) => {
+                self.terminal_display.render_git_ok(&status, repository, &self.indicators);
+            }
+            Err(GstError::CannotFetchRemote(e)) => {
+                self.terminal_display.render_err(&e, Some(&self.indicators), Some(path));
+            }
+            Err(GstError::FetchTimedOut(timeout)) => {
+                self.terminal_display.render_timeout(timeout, &self.indicators, path);
+            }
             Err(e) => {
-                self.terminal_display.render_err(&e.to_string(), Some(&self.indicators), Some(&path));
-                return;
+                self.terminal_display.render_err(&e.to_string(), Some(&self.indicators), Some(path));
             }
-        };
+        }
+    }
+}
 
-        for entry in dir_content {
-            // In case I f*ed up the base case, the recursion depth is limited to both 
-            // dimensions. Negative and positive.
-            // Where `max_height` corresponds to the negative limit.
-            if (self.rec_counter < self.max_height) || (self.rec_counter >= self.max_depth + 1) {
-                break;
-            }
+// Runs `git fetch` and `git status` for the given groups of repositories on a pool of
+// `jobs` workers. `on_result` is called in the order of the groups, no matter which worker
+// finishes first.
+// Worktrees of the same repository are in the same group, so their object store is
+// fetched only once.
+fn check_repositories(repositories: &[Repository], groups: &[Vec<usize>], jobs: u8, fetch_mode: FetchMode,
+                      branch_mode: BranchMode, mut on_result: impl FnMut(usize, Result<RepoStatus, GstError>)) {
+    let jobs = usize::from(jobs).clamp(1, groups.len().max(1));
+    let order: Vec<usize> = groups.iter().flatten().copied().collect();
+    let next_group = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let next_group = &next_group;
 
-            let entry = match entry {
-                Ok(v) => v,
-                Err(e) => {
-                    self.terminal_display.render_err(&e.to_string(), Some(&self.indicators), Some(&path));
+            scope.spawn(move || loop {
+                let idx = next_group.fetch_add(1, Ordering::Relaxed);
+                let Some(group) = groups.get(idx) else {
                     break;
+                };
+
+                let fetched = match fetch_mode {
+                    FetchMode::Fetch => git_fetch(&repositories[group[0]]),
+                    FetchMode::Offline => Ok(()),
+                };
+                for idx in group {
+                    let result = match &fetched {
+                        Ok(()) => git_status(&repositories[*idx], branch_mode),
+                        Err(e) => Err(e.clone()),
+                    };
+                    if sender.send((*idx, result)).is_err() {
+                        return;
+                    }
                 }
-            };
-            let path = entry.path();
+            });
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut next_to_render = 0;
+        for (idx, result) in receiver {
+            pending.insert(idx, result);
+            while let Some(result) = order.get(next_to_render).and_then(|v| pending.remove(v)) {
+                on_result(order[next_to_render], result);
+                next_to_render += 1;
+            }
+        }
+    });
+}
+
+// Events of the watcher, and the fetches in the background
+enum WatchEvent {
+    Changed(Vec<PathBuf>),
+    // The index of the group, and the result of its fetch
+    Fetched(usize, Result<(), GstError>),
+}
+
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+// Git dirs are watched recursively. Work trees directory by directory, so the directories
+// git or the exclude patterns ignore, e.g. `node_modules` or `target`, aren't watched at all.
+fn watch_paths(repositories: &[Repository], exclude: &[String]) -> Vec<(PathBuf, RecursiveMode)> {
+    let mut git_dirs: Vec<PathBuf> = repositories
+        .iter()
+        .flat_map(|v| [v.git_dir.clone(), v.common_dir.clone()])
+        .collect();
+    git_dirs.sort();
+
+    let mut watch_paths: Vec<(PathBuf, RecursiveMode)> = vec![];
+    for path in git_dirs {
+        if !watch_paths.iter().any(|(v, _)| path.starts_with(v)) {
+            watch_paths.push((path, RecursiveMode::Recursive));
+        }
+    }
+
+    let mut work_tree_dirs: BTreeSet<PathBuf> = BTreeSet::new();
+    for repository in repositories.iter().filter(|v| !v.bare) {
+        // Nested projects are part of the work tree of the outer one
+        if !work_tree_dirs.contains(&repository.work_tree) {
+            work_tree_dirs.extend(walk_work_tree(&repository.work_tree, &repository.work_tree, exclude));
+        }
+    }
+    watch_paths.extend(
+        work_tree_dirs
+            .into_iter()
+            .filter(|path| !watch_paths.iter().any(|(v, _)| path.starts_with(v)))
+            .map(|v| (v, RecursiveMode::NonRecursive))
+            .collect::<Vec<_>>()
+    );
+    watch_paths
+}
+
+// Directories created in a watched work tree after the watch started, which aren't ignored
+fn new_work_tree_dirs(exclude: &[String], watched: &BTreeSet<PathBuf>, paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut new_dirs = vec![];
+    for path in paths.iter().filter(|v| v.is_dir() && !watched.contains(*v)) {
+        if !path.parent().is_some_and(|v| watched.contains(v)) {
+            continue;
+        }
+        if let Some(work_tree) = path.ancestors().skip(1).find(|v| v.join(".git").exists()) {
+            new_dirs.extend(walk_work_tree(work_tree, path, exclude));
+        }
+    }
+    new_dirs
+}
+
+// `dir` and all directories below it, without `.git` and the ignored ones. Nested projects and
+// submodules are walked with their own ignore rules.
+fn walk_work_tree(work_tree: &Path, dir: &Path, exclude: &[String]) -> Vec<PathBuf> {
+    let excludes = ExcludePatterns::load(exclude, work_tree);
+    let ignored = ignored_dirs(work_tree, dir);
+    let mut dirs = vec![];
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        if dir.file_name().is_some_and(|v| v == ".git") || ignored.contains(&dir) || excludes.is_excluded(work_tree, &dir) {
+            continue;
+        }
+        if dir != work_tree && dir.join(".git").exists() {
+            dirs.extend(walk_work_tree(&dir, &dir, exclude));
+            continue;
+        }
+        if let Ok(entries) = fs::read_dir(&dir) {
+            pending.extend(
+                entries
+                    .filter_map(|v| v.ok())
+                    .filter(|v| v.file_type().is_ok_and(|v| v.is_dir()))
+                    .map(|v| v.path())
+            );
+        }
+        dirs.push(dir);
+    }
+    dirs
+}
+
+// Directories below `dir`, which are ignored by git as a whole
+fn ignored_dirs(work_tree: &Path, dir: &Path) -> HashSet<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(work_tree)
+        .args(["ls-files", "-z", "--others", "--ignored", "--exclude-standard", "--directory", "--"])
+        .arg(dir)
+        .output();
+    match output {
+        Ok(v) if v.status.success() => String::from_utf8_lossy(&v.stdout)
+            .split('\0')
+            .filter_map(|v| v.strip_suffix('/'))
+            .map(|v| work_tree.join(v))
+            .collect(),
+        _ => HashSet::new(),
+    }
+}
+
+fn affected_repositories(repositories: &[Repository], paths: &[PathBuf]) -> Vec<usize> {
+    repositories
+        .iter()
+        .enumerate()
+        .filter(|(_, repository)| {
+            let roots = [&repository.work_tree, &repository.git_dir, &repository.common_dir];
+            paths.iter().any(|path| roots.iter().any(|v| path.starts_with(v)))
+        })
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+pub fn git_fetch(repository: &Repository) -> Result<(), GstError> {
+    let mut fetch_cmd = Command::new("git");
+    fetch_cmd.arg("--git-dir").arg(&repository.common_dir).arg("fetch");
+
+    let git_fetch = output_with_timeout(&mut fetch_cmd, repository.fetch_timeout)?;
+
+    let fetch_err = String::from_utf8_lossy(&git_fetch.stderr).to_string();
+
+    if fetch_err.contains("fatal") {
+        return Err(GstError::CannotFetchRemote(fetch_err));
+    }
+    Ok(())
+}
+
+// Bare repositories have no files and no upstream. What's left is the branch HEAD points
+// to, the commits fetched but not yet on any local branch, and the time of the last update.
+fn bare_status(repository: &Repository) -> Result<RepoStatus, GstError> {
+    let git_dir = &repository.common_dir;
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .arg("--git-dir")
+            .arg(git_dir)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|v| v.status.success())
+            .map(|v| String::from_utf8_lossy(&v.stdout).trim().to_string())
+    };
+
+    if git(&["rev-parse", "--git-dir"]).is_none() {
+        return Err(GstError::Git("Not a valid bare repository".to_string()));
+    }
+
+    let mut status = RepoStatus {
+        bare: true,
+        head: git(&["symbolic-ref", "-q", "--short", "HEAD"]),
+        oid: git(&["rev-parse", "-q", "--verify", "HEAD"]),
+        last_updated: last_updated(git_dir),
+        ..Default::default()
+    };
+
+    // Every line of `FETCH_HEAD` is `<oid>\t[not-for-merge]\t<description>`. Only the remote
+    // HEAD (the description is just the url) and branches are of interest, not tags or
+    // other refs a mirror fetches. A mirror fetches into its branches, so it's never behind.
+    let fetch_head = fs::read_to_string(git_dir.join("FETCH_HEAD")).unwrap_or_default();
+    let fetched: Vec<&str> = fetch_head
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let oid = fields.next()?;
+            let description = fields.nth(1)?;
+            (description.starts_with("branch '") || !description.contains(" of ")).then_some(oid)
+        })
+        .collect();
+    if !fetched.is_empty() {
+        let mut args = vec!["rev-list", "--count"];
+        args.extend(fetched);
+        args.extend(["--not", "--branches"]);
+        status.behind = git(&args).and_then(|v| v.parse().ok()).unwrap_or(0);
+    }
+    Ok(status)
+}
+
+// The last fetch, or for a fresh clone the time it was created. Seconds since the epoch.
+fn last_updated(git_dir: &Path) -> Option<u64> {
+    ["FETCH_HEAD", "packed-refs"]
+        .iter()
+        .filter_map(|v| fs::metadata(git_dir.join(v)).and_then(|v| v.modified()).ok())
+        .max()
+        .and_then(|v| v.duration_since(UNIX_EPOCH).ok())
+        .map(|v| v.as_secs())
+}
+
+pub fn git_status(repository: &Repository, branch_mode: BranchMode) -> Result<RepoStatus, GstError> {
+    if repository.bare {
+        return bare_status(repository);
+    }
+
+    let parent_path = match repository.work_tree.to_str() {
+        Some(v) => v,
+        None => return Err(GstError::Git("Could not determine path".to_string())),
+    };
+
+    // Without optional locks, `git status` doesn't refresh the index. This way it never
+    // interferes with git commands run by the user, and causes no events in watch mode.
+    let git_status = Command::new("git")
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .args(["-C", parent_path, "status", "--porcelain=v2", "--branch"])
+        .output()
+        .map_err(spawn_error)?;
+
+    let status_ok = String::from_utf8_lossy(&git_status.stdout).to_string();
+    let status_err = String::from_utf8_lossy(&git_status.stderr).to_string();
+
+    if !status_err.is_empty() {
+        return Err(GstError::Git(status_err));
+    }
+    let mut status = RepoStatus::parse(&status_ok)?;
+    status.operations = operations_in_progress(&repository.git_dir);
+    // All worktrees share the stash of the main repository, so it's only reported there
+    if !repository.is_linked_worktree() {
+        status.stashes = stash_count(&repository.common_dir);
+    }
+    if status.has_no_upstream() && status.oid.is_some() {
+        status.local_commits = local_commits(parent_path);
+    }
+    if branch_mode == BranchMode::All {
+        status.branches = branches(parent_path)?;
+    }
+    status.submodules = submodule_status(&repository.work_tree, &status.entries);
+    Ok(status)
+}
+
+#[derive(Serialize, Clone)]
+pub struct SubmoduleStatus {
+    pub path: String,
+    pub initialized: bool,
+    // The checked out commit differs from the one recorded in the superproject
+    pub commit_changed: bool,
+    pub modified: bool,
+    pub untracked: bool,
+}
+
+impl SubmoduleStatus {
+    pub fn is_clean(&self) -> bool {
+        self.initialized && !self.commit_changed && !self.modified && !self.untracked
+    }
+}
+
+// Reads the submodule paths from `.gitmodules`, e.g. `path = lib/foo`
+fn submodule_paths(work_tree: &Path) -> Vec<String> {
+    let Ok(gitmodules) = fs::read_to_string(work_tree.join(".gitmodules")) else {
+        return vec![];
+    };
+
+    gitmodules
+        .lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .filter(|(key, _)| key.trim() == "path")
+        .map(|(_, path)| path.trim().to_string())
+        .collect()
+}
+
+// Uninitialized submodules have no `.git`. Everything else is part of `git status`.
+fn submodule_status(work_tree: &Path, entries: &[StatusEntry]) -> Vec<SubmoduleStatus> {
+    submodule_paths(work_tree)
+        .into_iter()
+        .map(|path| {
+            let sub = entries
+                .iter()
+                .find(|v| v.path == path && v.sub.starts_with('S'))
+                .map(|v| v.sub.as_str())
+                .unwrap_or("S...");
+            SubmoduleStatus {
+                initialized: work_tree.join(&path).join(".git").exists(),
+                commit_changed: sub.chars().nth(1) == Some('C'),
+                modified: sub.chars().nth(2) == Some('M'),
+                untracked: sub.chars().nth(3) == Some('U'),
+                path,
+            }
+        })
+        .collect()
+}
+
+#[derive(Serialize, Clone)]
+pub struct BranchStatus {
+    pub name: String,
+    pub current: bool,
+    pub upstream: Option<String>,
+    pub ahead: u32,
+    pub behind: u32,
+    // Only without upstream: commits not on any remote-tracking branch
+    pub local_commits: Option<u32>,
+}
+
+impl BranchStatus {
+    pub fn never_pushed(&self) -> bool {
+        self.upstream.is_none()
+    }
+
+    pub fn has_unpushed_commits(&self) -> bool {
+        self.ahead > 0 || self.local_commits.unwrap_or(0) > 0
+    }
+}
+
+// Lists all local branches with their upstream, e.g. `*\0main\0origin/main\0ahead 1, behind 2`
+fn branches(work_tree: &str) -> Result<Vec<BranchStatus>, GstError> {
+    let format = "%(HEAD)%00%(refname:short)%00%(upstream:short)%00%(upstream:track,nobracket)";
+    let for_each_ref = Command::new("git")
+        .args(["-C", work_tree, "for-each-ref", "--format", format, "refs/heads"])
+        .output()
+        .map_err(spawn_error)?;
+
+    if !for_each_ref.status.success() {
+        return Err(GstError::Git(String::from_utf8_lossy(&for_each_ref.stderr).to_string()));
+    }
+
+    let has_remotes = has_remotes(work_tree);
+    let mut branches = vec![];
+    for line in String::from_utf8_lossy(&for_each_ref.stdout).lines() {
+        let fields: Vec<&str> = line.split('\0').collect();
+        let [head, name, upstream, track] = fields[..] else {
+            continue;
+        };
+
+        let mut branch = BranchStatus {
+            name: name.to_string(),
+            current: head == "*",
+            upstream: (!upstream.is_empty()).then(|| upstream.to_string()),
+            ahead: 0,
+            behind: 0,
+            local_commits: None,
+        };
+        for count in track.split(", ") {
+            if let Some(v) = count.strip_prefix("ahead ") {
+                branch.ahead = v.parse().unwrap_or(0);
+            }
+            if let Some(v) = count.strip_prefix("behind ") {
+                branch.behind = v.parse().unwrap_or(0);
+            }
+        }
+        if branch.never_pushed() && has_remotes {
+            let rev_list = Command::new("git")
+                .args(["-C", work_tree, "rev-list", "--count", name, "--not", "--remotes"])
+                .output()
+                .map_err(spawn_error)?;
+            branch.local_commits = String::from_utf8_lossy(&rev_list.stdout).trim().parse().ok();
+        }
+        branches.push(branch);
+    }
+    Ok(branches)
+}
+
+// Every stash entry is a line in the reflog of `refs/stash`
+fn stash_count(common_dir: &Path) -> u32 {
+    match fs::read_to_string(common_dir.join("logs").join("refs").join("stash")) {
+        Ok(v) => v.lines().filter(|line| !line.is_empty()).count() as u32,
+        Err(_) => 0,
+    }
+}
+
+// Counts the commits of HEAD, which are not on the default branch of `origin`. If the
+// remote has no default branch, all remote-tracking branches are taken into account.
+// Without any remote, there's nothing to push to, so nothing is counted.
+fn local_commits(work_tree: &str) -> Option<u32> {
+    if !has_remotes(work_tree) {
+        return None;
+    }
+
+    let default_branch = Command::new("git")
+        .args(["-C", work_tree, "symbolic-ref", "-q", "--short", "refs/remotes/origin/HEAD"])
+        .output()
+        .ok()
+        .filter(|v| v.status.success())
+        .map(|v| String::from_utf8_lossy(&v.stdout).trim().to_string());
+
+    let exclude = default_branch.unwrap_or_else(|| "--remotes".to_string());
+
+    let rev_list = Command::new("git")
+        .args(["-C", work_tree, "rev-list", "--count", "HEAD", "--not", &exclude])
+        .output()
+        .ok()?;
+    if !rev_list.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&rev_list.stdout).trim().parse().ok()
+}
 
-            if path.ends_with(".git") {
-                match self.git_status(&path) {
-                    Ok(v) => {
-                        self.terminal_display.render_git_ok(v.to_string(), &path, &self.indicators, &mut self.project_state);
+// Has at least one remote configured
+fn has_remotes(work_tree: &str) -> bool {
+    Command::new("git")
+        .args(["-C", work_tree, "remote"])
+        .output()
+        .is_ok_and(|v| v.status.success() && !v.stdout.trim_ascii().is_empty())
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Merge,
+    Rebase,
+    CherryPick,
+    Revert,
+    Bisect,
+}
+
+// Git leaves these files/dirs inside the git dir, as long as an operation is unfinished
+fn operations_in_progress(git_dir: &Path) -> Vec<Operation> {
+    let markers = [
+        ("MERGE_HEAD", Operation::Merge),
+        ("rebase-merge", Operation::Rebase),
+        ("rebase-apply", Operation::Rebase),
+        ("CHERRY_PICK_HEAD", Operation::CherryPick),
+        ("REVERT_HEAD", Operation::Revert),
+        ("BISECT_LOG", Operation::Bisect),
+    ];
+
+    let mut operations = vec![];
+    for (marker, operation) in markers {
+        if git_dir.join(marker).exists() && !operations.contains(&operation) {
+            operations.push(operation);
+        }
+    }
+    operations
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Ordinary,
+    Renamed,
+    Unmerged,
+    Untracked,
+}
+
+#[derive(Serialize, Clone)]
+pub struct StatusEntry {
+    pub kind: EntryKind,
+    // Index (X) and worktree (Y) state, `.` means unmodified. Untracked files are `??`.
+    pub xy: String,
+    // `N...` for files, `S<c><m><u>` for submodules: commit changed, modified, untracked
+    pub sub: String,
+    pub path: String,
+    pub orig_path: Option<String>,
+}
+
+impl StatusEntry {
+    fn new(kind: EntryKind, fields: &str, path: &str, orig_path: Option<&str>) -> StatusEntry {
+        StatusEntry {
+            kind,
+            xy: fields.chars().take(2).collect(),
+            sub: fields.split(' ').nth(1).unwrap_or("N...").to_string(),
+            path: path.to_string(),
+            orig_path: orig_path.map(|v| v.to_string()),
+        }
+    }
+
+    pub fn x(&self) -> char {
+        self.xy.chars().next().unwrap_or('.')
+    }
+
+    pub fn y(&self) -> char {
+        self.xy.chars().nth(1).unwrap_or('.')
+    }
+}
+
+// The parsed output of `git status --porcelain=v2 --branch`
+#[derive(Serialize, Default, Clone)]
+pub struct RepoStatus {
+    // `None` before the initial commit
+    pub oid: Option<String>,
+    // `None` if HEAD is detached
+    pub head: Option<String>,
+    pub upstream: Option<String>,
+    pub ahead: u32,
+    pub behind: u32,
+    pub entries: Vec<StatusEntry>,
+    // Not part of `git status`, detected from the git dir
+    pub operations: Vec<Operation>,
+    // Only without upstream: commits not on the remote default branch
+    pub local_commits: Option<u32>,
+    pub stashes: u32,
+    // Only in `BranchMode::All`
+    pub branches: Vec<BranchStatus>,
+    pub submodules: Vec<SubmoduleStatus>,
+    pub bare: bool,
+    // Only for bare repositories: seconds since the epoch
+    pub last_updated: Option<u64>,
+}
+
+impl RepoStatus {
+    pub fn parse(git_output: &str) -> Result<RepoStatus, GstError> {
+        let mut status = RepoStatus::default();
+        let mut found_header = false;
+
+        for line in git_output.lines() {
+            if let Some(header) = line.strip_prefix("# ") {
+                let (key, value) = header.split_once(' ').unwrap_or((header, ""));
+                match key {
+                    "branch.oid" => {
+                        found_header = true;
+                        status.oid = (value != "(initial)").then(|| value.to_string());
                     }
-                    Err(e) => {
-                        self.terminal_display.render_err(&e.to_string(), Some(&self.indicators), Some(&path));
+                    "branch.head" => status.head = (value != "(detached)").then(|| value.to_string()),
+                    "branch.upstream" => status.upstream = Some(value.to_string()),
+                    "branch.ab" => {
+                        for count in value.split(' ') {
+                            if let Some(v) = count.strip_prefix('+') {
+                                status.ahead = v.parse().unwrap_or(0);
+                            }
+                            if let Some(v) = count.strip_prefix('-') {
+                                status.behind = v.parse().unwrap_or(0);
+                            }
+                        }
                     }
-                };
-                self.git_dir_state = Information::FoundGitDir;
+                    _ => (),
+                }
+                continue;
             }
 
-            if path.is_dir() {
-                self.rec_counter += 1;
-                self.traversal(&path);
+            let entry = match line.split_once(' ') {
+                // 1 <XY> <sub> <mH> <mI> <mW> <hH> <hI> <path>
+                Some(("1", fields)) => fields.splitn(8, ' ').collect::<Vec<_>>()
+                    .get(7)
+                    .map(|path| StatusEntry::new(EntryKind::Ordinary, fields, path, None)),
+                // 2 <XY> <sub> <mH> <mI> <mW> <hH> <hI> <X><score> <path><tab><origPath>
+                Some(("2", fields)) => fields.splitn(9, ' ').collect::<Vec<_>>()
+                    .get(8)
+                    .map(|paths| {
+                        let (path, orig_path) = paths.split_once('\t').unwrap_or((paths, ""));
+                        StatusEntry::new(EntryKind::Renamed, fields, path, Some(orig_path))
+                    }),
+                // u <XY> <sub> <m1> <m2> <m3> <mW> <h1> <h2> <h3> <path>
+                Some(("u", fields)) => fields.splitn(10, ' ').collect::<Vec<_>>()
+                    .get(9)
+                    .map(|path| StatusEntry::new(EntryKind::Unmerged, fields, path, None)),
+                Some(("?", path)) => Some(StatusEntry::new(EntryKind::Untracked, "??", path, None)),
+                _ => None,
             };
+
+            if let Some(entry) = entry {
+                status.entries.push(entry);
+            }
         }
-        self.rec_counter -= 1;
+
+        if !found_header {
+            return Err(GstError::Git("Cannot read Git output. Maybe Git is not installed, \
+                                         or not in $PATH".to_string()));
+        }
+        Ok(status)
     }
 
-    fn git_status(&mut self, project_path: &PathBuf) -> Result<String, String> {
-        let project_path = project_path.parent();
-        let parent_path = match project_path {
-            Some(v) => v.to_str().unwrap(),
-            None => "",
-        };
+    pub fn has_files(&self) -> bool {
+        !self.entries.is_empty()
+    }
+
+    // The checked out branch is already covered by the status itself
+    pub fn unpushed_branches(&self) -> Vec<&BranchStatus> {
+        self.branches
+            .iter()
+            .filter(|v| !v.current && v.has_unpushed_commits())
+            .collect()
+    }
+
+    pub fn is_detached(&self) -> bool {
+        self.head.is_none()
+    }
 
-        if parent_path == "" {
-            return Err("Could not determine path".to_string());
+    pub fn has_no_upstream(&self) -> bool {
+        !self.bare && self.head.is_some() && self.upstream.is_none()
+    }
+
+    // A branch without upstream, with commits, which are on no remote. Without any
+    // commits of its own, `⇡` is only informational.
+    pub fn has_local_commits(&self) -> bool {
+        self.has_no_upstream() && self.local_commits.unwrap_or(0) > 0
+    }
+
+    // Commits on a detached HEAD or on a branch without upstream are never pushed, so
+    // neither of them is considered up to date.
+    pub fn is_clean(&self) -> bool {
+        !self.has_files()
+            && self.ahead == 0
+            && self.behind == 0
+            && self.operations.is_empty()
+            && self.stashes == 0
+            && self.unpushed_branches().is_empty()
+            && self.submodules.iter().all(|v| v.is_clean())
+            && !self.is_detached()
+            && !self.has_local_commits()
+    }
+
+    pub fn file_changes(&self) -> FileChanges {
+        let mut files = FileChanges::default();
+        // Submodules are reported on their own, and aren't counted as changed files
+        for entry in self.entries.iter().filter(|v| !v.sub.starts_with('S')) {
+            match entry.kind {
+                EntryKind::Untracked => files.untracked += 1,
+                EntryKind::Unmerged => files.unmerged += 1,
+                EntryKind::Ordinary | EntryKind::Renamed => {
+                    if entry.x() != '.' {
+                        files.staged += 1;
+                    }
+                    if entry.y() != '.' {
+                        files.unstaged += 1;
+                    }
+                    if matches!(entry.y(), 'M' | 'T') {
+                        files.modified += 1;
+                    }
+                    if entry.x() == 'D' || entry.y() == 'D' {
+                        files.deleted += 1;
+                    }
+                    if entry.kind == EntryKind::Renamed {
+                        files.renamed += 1;
+                    }
+                }
+            }
         }
+        files
+    }
+}
 
-        let git_fetch = Command::new("git")
-            .args(["--git-dir", format!("{}/.git", parent_path).as_str(), "fetch"])
-            .output()
-            .unwrap();
+#[derive(Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchResult {
+    Ok,
+    Skipped,
+    Failed,
+    TimedOut,
+}
+
+#[derive(Serialize, Default)]
+pub struct FileChanges {
+    // Any change in the index, resp. in the working tree
+    pub staged: u32,
+    pub unstaged: u32,
+    // Not staged modifications
+    pub modified: u32,
+    // Staged or not staged
+    pub deleted: u32,
+    pub renamed: u32,
+    pub untracked: u32,
+    pub unmerged: u32,
+}
 
-        let fetch_err = String::from_utf8(git_fetch.stderr).unwrap();
+// Everything known about a single repository after a scan. This is the model for the
+// structured output, and is independent of how the terminal renders it.
+#[derive(Serialize)]
+pub struct RepoReport {
+    pub path: PathBuf,
+    pub project: String,
+    pub canonical_path: PathBuf,
+    pub worktree_of: Option<PathBuf>,
+    pub bare: bool,
+    pub last_updated: Option<u64>,
+    pub clean: bool,
+    pub branch: Option<String>,
+    pub detached: bool,
+    pub upstream: Option<String>,
+    pub ahead: u32,
+    pub behind: u32,
+    pub local_commits: Option<u32>,
+    pub stashes: u32,
+    pub files: FileChanges,
+    pub branches: Vec<BranchStatus>,
+    pub submodules: Vec<SubmoduleStatus>,
+    pub operations: Vec<Operation>,
+    pub fetch: FetchResult,
+    pub error: Option<String>,
+}
+
+impl RepoReport {
+    pub fn new(repository: &Repository, result: Result<RepoStatus, GstError>, fetch_mode: FetchMode) -> RepoReport {
+        let path = repository.work_tree.clone();
+        let project = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let mut report = RepoReport {
+            path,
+            project,
+            canonical_path: repository.canonical_work_tree.clone(),
+            worktree_of: repository.worktree_of.clone(),
+            bare: repository.bare,
+            last_updated: None,
+            clean: false,
+            branch: None,
+            detached: false,
+            upstream: None,
+            ahead: 0,
+            behind: 0,
+            local_commits: None,
+            stashes: 0,
+            files: FileChanges::default(),
+            branches: vec![],
+            submodules: vec![],
+            operations: vec![],
+            fetch: match fetch_mode {
+                FetchMode::Fetch => FetchResult::Ok,
+                FetchMode::Offline => FetchResult::Skipped,
+            },
+            error: None,
+        };
 
-        if fetch_err.contains("fatal") {
-            self.project_state.info_state = Information::CannotFetchRemote;
-            return Err(fetch_err);
+        match result {
+            Ok(status) => {
+                report.clean = status.is_clean();
+                report.files = status.file_changes();
+                report.detached = status.is_detached();
+                report.local_commits = status.local_commits;
+                report.stashes = status.stashes;
+                report.branches = status.branches;
+                report.submodules = status.submodules;
+                report.branch = status.head;
+                report.upstream = status.upstream;
+                report.ahead = status.ahead;
+                report.behind = status.behind;
+                report.operations = status.operations;
+                report.last_updated = status.last_updated;
+            }
+            Err(GstError::CannotFetchRemote(e)) => {
+                report.fetch = FetchResult::Failed;
+                report.error = Some(e.trim().to_string());
+            }
+            Err(GstError::FetchTimedOut(timeout)) => {
+                report.fetch = FetchResult::TimedOut;
+                report.error = Some(format!("Fetch timed out after {}s", timeout.as_secs()));
+            }
+            Err(e) => report.error = Some(e.to_string().trim().to_string()),
         }
+        report
+    }
 
-        let git_status = Command::new("git")
-            .args(["-C", parent_path, "status", "-b", "--porcelain"])
-            .output()
-            .unwrap();
+    pub fn is_clean(&self) -> bool {
+        self.clean
+    }
+}
+
+// Makes sure `git` can be executed at all, before any project is checked
+pub fn check_git_client() -> Result<(), GstError> {
+    let git = Command::new("git")
+        .args(["--version"])
+        .output()
+        .map_err(spawn_error)?;
 
-        let status_ok = String::from_utf8(git_status.stdout).unwrap();
-        let status_err = String::from_utf8(git_status.stderr).unwrap();
+    match String::from_utf8_lossy(&git.stdout).to_lowercase().contains("git") {
+        true => Ok(()),
+        false => Err(GstError::GitMissing),
+    }
+}
+
+fn spawn_error(e: io::Error) -> GstError {
+    match e.kind() {
+        io::ErrorKind::NotFound => GstError::GitMissing,
+        _ => GstError::Git(e.to_string()),
+    }
+}
+
+// Like `Command::output()`, but kills the child process once `timeout` has passed.
+// On Unix, the child runs in its own process group, so its children (e.g. `ssh`) are killed too.
+fn output_with_timeout(command: &mut Command, timeout: Option<Duration>) -> Result<Output, GstError> {
+    let Some(timeout) = timeout else {
+        return command.output().map_err(spawn_error);
+    };
+
+    #[cfg(target_family = "unix")]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+
+    // The pipes are drained by separate threads, otherwise a chatty child could block
+    // on a full pipe and would never exit.
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+    let stdout_reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stdout.read_to_end(&mut buf);
+        buf
+    });
+    let stderr_reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf);
+        buf
+    });
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| GstError::Git(e.to_string()))? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            kill_process_group(&mut child);
+            let _ = child.wait();
+            // Don't join the readers: a grandchild, which ignores the signal, may still hold the pipes open
+            return Err(GstError::FetchTimedOut(timeout));
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout_reader.join().unwrap_or_default(),
+        stderr: stderr_reader.join().unwrap_or_default(),
+    })
+}
 
-        if !status_err.is_empty() {
-            return Err(status_err);
+#[cfg(target_family = "unix")]
+fn kill_process_group(child: &mut Child) {
+    // The process group ID equals the process ID of the child, see `process_group(0)`
+    match i32::try_from(child.id()) {
+        // SAFETY: `kill` has no memory safety requirements
+        Ok(pgid) => unsafe {
+            libc::kill(-pgid, libc::SIGKILL);
+        },
+        Err(_) => {
+            let _ = child.kill();
         }
-        Ok(status_ok)
     }
 }
 
-pub enum Information {
-    AllGreen,
-    Warning,
-    FoundGitDir,
-    NotFoundGitDir,
-    NotValidPath,
-    CannotFetchRemote,
+#[cfg(not(target_family = "unix"))]
+fn kill_process_group(child: &mut Child) {
+    let _ = child.kill();
 }
 
-pub struct InformationHandler {
-    pub info_state: Information,
+// Counts of all checked projects. A project, which is e.g. ahead and behind, is counted
+// for both.
+#[derive(Default, Clone, Copy)]
+pub struct Summary {
+    pub projects: u32,
+    pub clean: u32,
+    // Changed files, stashes, unfinished operations, submodules or a detached HEAD
+    pub dirty: u32,
+    // Also commits without upstream, and other branches with unpushed commits
+    pub ahead: u32,
+    pub behind: u32,
+    pub fetch_failed: u32,
+    pub errored: u32,
+    // Project paths, which don't exist, or contain no repository
+    pub invalid_paths: u32,
 }
 
-impl InformationHandler {
-    pub fn new() -> InformationHandler {
-        let info_handler = InformationHandler {
-            info_state: Information::AllGreen,
+impl Summary {
+    pub fn add(&mut self, result: &Result<RepoStatus, GstError>) -> &mut Self {
+        self.projects += 1;
+        let status = match result {
+            Ok(v) => v,
+            Err(GstError::CannotFetchRemote(_) | GstError::FetchTimedOut(_)) => {
+                self.fetch_failed += 1;
+                return self;
+            }
+            Err(_) => {
+                self.errored += 1;
+                return self;
+            }
         };
-        info_handler
-    }
+        if status.is_clean() {
+            self.clean += 1;
+            return self;
+        }
 
-    pub fn set(&mut self, state: Information) -> &Self {
-        self.info_state = state;
+        let ahead = status.ahead > 0 || status.has_local_commits() || !status.unpushed_branches().is_empty();
+        let behind = status.behind > 0;
+        let dirty = status.has_files()
+            || status.stashes > 0
+            || !status.operations.is_empty()
+            || status.submodules.iter().any(|v| !v.is_clean())
+            || status.is_detached();
+        self.ahead += u32::from(ahead);
+        self.behind += u32::from(behind);
+        self.dirty += u32::from(dirty || (!ahead && !behind));
         self
     }
+
+    pub fn is_all_clean(&self) -> bool {
+        self.projects > 0 && self.clean == self.projects && !self.has_errors()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.fetch_failed > 0 || self.errored > 0 || self.invalid_paths > 0
+    }
+
+    // `--exit-code`: clean, changes found, or errors occurred
+    pub fn exit_code(&self) -> i32 {
+        if self.has_errors() {
+            return error::EXIT_ERROR;
+        }
+        match self.clean == self.projects {
+            true => error::EXIT_CLEAN,
+            false => error::EXIT_CHANGES,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Captured from `git status --porcelain=v2 --branch`
+    const INITIAL: &str = "\
+# branch.oid (initial)
+# branch.head main
+";
+
+    const CLEAN: &str = "\
+# branch.oid 85d3df27567f1861765d8fd239b5058108560a5a
+# branch.head main
+# branch.upstream origin/main
+# branch.ab +0 -0
+";
+
+    const AHEAD: &str = "\
+# branch.oid 85d3df27567f1861765d8fd239b5058108560a5a
+# branch.head main
+# branch.upstream origin/main
+# branch.ab +2 -0
+";
+
+    const CHANGES: &str = "\
+# branch.oid 85d3df27567f1861765d8fd239b5058108560a5a
+# branch.head main
+# branch.upstream origin/main
+# branch.ab +3 -12
+1 .D N... 100644 100644 000000 f2ad6c76f0115a6ba5b00456a849810e7ec0af20 f2ad6c76f0115a6ba5b00456a849810e7ec0af20 del.txt
+1 .M N... 100644 100644 100644 61780798228d17af2d34fce4cfbdf35556832472 61780798228d17af2d34fce4cfbdf35556832472 mod file.txt
+2 R. N... 100644 100644 100644 78981922613b2afb6025042ff6bd878ac1994e85 78981922613b2afb6025042ff6bd878ac1994e85 R100 new name.txt\told name.txt
+1 A. N... 000000 100644 100644 0000000000000000000000000000000000000000 b4785957bc986dc39c629de9fac9df46972c00fc staged.txt
+1 .M SC.. 160000 160000 160000 4bcfe98e640c8284511312660fb8709b0afa888e 4bcfe98e640c8284511312660fb8709b0afa888e lib/sub
+? new file.txt
+";
+
+    const CONFLICT: &str = "\
+# branch.oid 2a0ab565411c26f543cf778c7f8234d70bffd518
+# branch.head main
+u UU N... 100644 100644 100644 100644 4bcfe98e640c8284511312660fb8709b0afa888e 28ce6a8b26aa170e1de65536fe8abe1832bd3242 13e7564ea0c889e81bcba6f8e496b2a74cdb32fa c d.txt
+";
+
+    const DETACHED: &str = "\
+# branch.oid 2a0ab565411c26f543cf778c7f8234d70bffd518
+# branch.head (detached)
+";
+
+    #[test]
+    fn parse_initial_commit() {
+        let status = RepoStatus::parse(INITIAL).unwrap();
+        assert_eq!(status.oid, None);
+        assert_eq!(status.head.as_deref(), Some("main"));
+        assert_eq!(status.upstream, None);
+        assert!(status.has_no_upstream());
+        assert!(!status.has_local_commits());
+        assert!(!status.has_files());
+        assert!(status.is_clean());
+    }
+
+    #[test]
+    fn parse_detached_head() {
+        let status = RepoStatus::parse(DETACHED).unwrap();
+        assert_eq!(status.oid.as_deref(), Some("2a0ab565411c26f543cf778c7f8234d70bffd518"));
+        assert_eq!(status.head, None);
+        assert!(status.is_detached());
+        assert!(!status.has_no_upstream());
+        assert!(!status.is_clean());
+    }
+
+    #[test]
+    fn parse_upstream_and_ahead_behind() {
+        let status = RepoStatus::parse(CHANGES).unwrap();
+        assert_eq!(status.head.as_deref(), Some("main"));
+        assert_eq!(status.upstream.as_deref(), Some("origin/main"));
+        assert_eq!(status.ahead, 3);
+        assert_eq!(status.behind, 12);
+        assert!(!status.has_no_upstream());
+    }
+
+    #[test]
+    fn parse_entries() {
+        let status = RepoStatus::parse(CHANGES).unwrap();
+        let entries: Vec<(EntryKind, &str, &str, &str, Option<&str>)> = status.entries
+            .iter()
+            .map(|v| (v.kind, v.xy.as_str(), v.sub.as_str(), v.path.as_str(), v.orig_path.as_deref()))
+            .collect();
+        assert_eq!(entries, vec![
+            (EntryKind::Ordinary, ".D", "N...", "del.txt", None),
+            (EntryKind::Ordinary, ".M", "N...", "mod file.txt", None),
+            (EntryKind::Renamed, "R.", "N...", "new name.txt", Some("old name.txt")),
+            (EntryKind::Ordinary, "A.", "N...", "staged.txt", None),
+            (EntryKind::Ordinary, ".M", "SC..", "lib/sub", None),
+            (EntryKind::Untracked, "??", "N...", "new file.txt", None),
+        ]);
+    }
+
+    #[test]
+    fn parse_unmerged_entry() {
+        let status = RepoStatus::parse(CONFLICT).unwrap();
+        assert_eq!(status.entries.len(), 1);
+        let entry = &status.entries[0];
+        assert_eq!(entry.kind, EntryKind::Unmerged);
+        assert_eq!(entry.xy, "UU");
+        assert_eq!(entry.path, "c d.txt");
+        assert_eq!(status.file_changes().unmerged, 1);
+    }
+
+    #[test]
+    fn file_changes_are_counted_per_indicator() {
+        let files = RepoStatus::parse(CHANGES).unwrap().file_changes();
+        assert_eq!(files.staged, 2);
+        assert_eq!(files.unstaged, 2);
+        assert_eq!(files.modified, 1);
+        assert_eq!(files.deleted, 1);
+        assert_eq!(files.renamed, 1);
+        assert_eq!(files.untracked, 1);
+        assert_eq!(files.unmerged, 0);
+    }
+
+    #[test]
+    fn parse_rejects_output_without_header() {
+        assert!(matches!(RepoStatus::parse(""), Err(GstError::Git(_))));
+        assert!(matches!(RepoStatus::parse("fatal: not a git repository\n"), Err(GstError::Git(_))));
+    }
+
+    fn summary_of(results: &[Result<RepoStatus, GstError>]) -> Summary {
+        let mut summary = Summary::default();
+        for result in results {
+            summary.add(result);
+        }
+        summary
+    }
+
+    #[test]
+    fn summary_of_clean_projects() {
+        let summary = summary_of(&[RepoStatus::parse(CLEAN), RepoStatus::parse(INITIAL)]);
+        assert_eq!((summary.projects, summary.clean, summary.dirty, summary.ahead), (2, 2, 0, 0));
+        assert!(summary.is_all_clean());
+        assert_eq!(summary.exit_code(), error::EXIT_CLEAN);
+    }
+
+    #[test]
+    fn summary_of_dirty_project() {
+        let summary = summary_of(&[RepoStatus::parse(CLEAN), RepoStatus::parse(CONFLICT)]);
+        assert_eq!((summary.clean, summary.dirty, summary.ahead, summary.behind), (1, 1, 0, 0));
+        assert!(!summary.is_all_clean());
+        assert_eq!(summary.exit_code(), error::EXIT_CHANGES);
+    }
+
+    #[test]
+    fn summary_of_ahead_project() {
+        let summary = summary_of(&[RepoStatus::parse(AHEAD)]);
+        assert_eq!((summary.clean, summary.dirty, summary.ahead, summary.behind), (0, 0, 1, 0));
+        assert!(!summary.is_all_clean());
+        assert_eq!(summary.exit_code(), error::EXIT_CHANGES);
+    }
+
+    #[test]
+    fn summary_of_failed_fetch() {
+        let summary = summary_of(&[
+            RepoStatus::parse(CLEAN),
+            Err(GstError::CannotFetchRemote("fatal: Could not read from remote repository.".to_string())),
+            Err(GstError::FetchTimedOut(Duration::from_secs(30))),
+        ]);
+        assert_eq!((summary.projects, summary.clean, summary.fetch_failed, summary.errored), (3, 1, 2, 0));
+        assert!(!summary.is_all_clean());
+        assert_eq!(summary.exit_code(), error::EXIT_ERROR);
+    }
+
+    #[test]
+    fn summary_with_invalid_path() {
+        let mut summary = summary_of(&[RepoStatus::parse(CLEAN)]);
+        summary.invalid_paths += 1;
+        assert!(summary.has_errors());
+        assert!(!summary.is_all_clean());
+        assert_eq!(summary.exit_code(), error::EXIT_ERROR);
+    }
+
+    #[test]
+    fn summary_without_projects() {
+        let summary = Summary::default();
+        assert!(!summary.is_all_clean());
+        assert_eq!(summary.exit_code(), error::EXIT_CLEAN);
+    }
 }
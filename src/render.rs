@@ -1,8 +1,9 @@
-use std::{process::exit, path::PathBuf};
+use std::{path::Path, time::{Duration, SystemTime, UNIX_EPOCH}};
 
 use crate::colors::{TerminalColor, Color};
+use crate::discovery::Repository;
 use crate::indicators::Indicators;
-use crate::provider::{Information, InformationHandler};
+use crate::provider::{BranchStatus, Operation, RepoReport, RepoStatus, SubmoduleStatus, Summary};
 
 
 #[derive(Copy, Clone, PartialEq)]
@@ -10,94 +11,337 @@ pub enum VerboseMode {
     Verbose1,
     Verbose2,
     Nothing,
+    // Not even errors are printed. Only the exit code tells the result.
+    Quiet,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    // A single JSON array, printed after all projects are checked
+    Json,
+    // One JSON object per line, printed as soon as a project is checked
+    Ndjson,
+}
+
+#[derive(Clone)]
 pub struct TerminalDisplay {
     pub terminal_color: TerminalColor,
     pub verbose_mode: VerboseMode,
+    pub stale_remote: bool,
+    pub show_counts: bool,
+    pub show_file_counts: bool,
+    // The line of a main repository, which was skipped as up to date. It's printed as soon
+    // as one of its linked worktrees is, so the worktree isn't listed below another project.
+    skipped_main: Option<String>,
 }
 
 impl TerminalDisplay {
-    pub fn new(verbose_mode: VerboseMode) -> TerminalDisplay {
-        let terminal_display = TerminalDisplay {
+    pub fn new(verbose_mode: VerboseMode, stale_remote: bool, show_counts: bool,
+               show_file_counts: bool) -> TerminalDisplay {
+        TerminalDisplay {
             terminal_color: TerminalColor::new(),
             verbose_mode,
-        };
-        terminal_display
+            stale_remote,
+            show_counts,
+            show_file_counts,
+            skipped_main: None,
+        }
     }
 
-    pub fn render_git_ok(&mut self, git_output: String, path: &PathBuf, indicators: &Indicators,
-                     project_state: &mut InformationHandler) -> () {
-        let git_response: Vec<&str> = git_output.lines().collect();
-        let mut indicator = String::new();
+    pub fn render_git_ok(&mut self, status: &RepoStatus, repository: &Repository, indicators: &Indicators) {
+        let repo_ok = status.is_clean();
+        let skipped = self.verbose_mode == VerboseMode::Nothing && repo_ok;
+        if skipped && repository.nested {
+            return;
+        }
+
+        // Every part is an indicator, and whether it's followed by a count
+        let mut parts: Vec<(String, bool)> = vec![];
 
-        if git_response.is_empty() {
-            let err_msg = "Cannot read Git output. Maybe Git is not installed, or not in $PATH";
-            eprintln!("{} - {}", path.parent().unwrap().to_str().unwrap(), err_msg);
-            exit(1);
+        // Unfinished operations come first, since they are the most dangerous state
+        for operation in &status.operations {
+            let s = match operation {
+                Operation::Merge => indicators.merge,
+                Operation::Rebase => indicators.rebase,
+                Operation::CherryPick => indicators.cherry_pick,
+                Operation::Revert => indicators.revert,
+                Operation::Bisect => indicators.bisect,
+            };
+            parts.push((self.terminal_color.color(s, Color::Red), false));
         }
 
-        // Means: no unstaged, untracked, staged, tracked or new files are present
-        let no_files = git_response.len() == 1;
-        let not_ahead_and_not_behind = !git_response[0].contains("[ahead")
-                                   && !git_response[0].contains("[behind");
-        let repo_ok = no_files && not_ahead_and_not_behind;
+        if status.ahead > 0 {
+            let s = self.count_variant(indicators.ahead, indicators.ahead_count, status.ahead);
+            parts.push((self.terminal_color.color(&s, Color::Yellow), self.show_counts));
+        }
 
-        if self.verbose_mode == VerboseMode::Nothing && repo_ok {
-            return;
+        if status.behind > 0 {
+            let s = self.count_variant(indicators.behind, indicators.behind_count, status.behind);
+            parts.push((self.terminal_color.color(&s, Color::Yellow), self.show_counts));
+        }
+
+        if status.is_detached() {
+            parts.push((self.terminal_color.color(indicators.detached, Color::Yellow), false));
         }
 
-        indicator.push('[');
+        if status.has_no_upstream() {
+            let s = match status.local_commits {
+                Some(v) if v > 0 => self.count_variant(indicators.no_upstream, indicators.no_upstream_count, v),
+                _ => indicators.no_upstream.to_string(),
+            };
+            let has_count = self.show_counts && status.local_commits.unwrap_or(0) > 0;
+            parts.push((self.terminal_color.color(&s, Color::Yellow), has_count));
+        }
 
-        if git_response[0].contains("[ahead") {
-            let s = self.terminal_color.color(indicators.ahead, Color::Yellow);
-            indicator.push_str(&s);
-            project_state.set(Information::Warning);
+        let files = status.file_changes();
+        let file_indicators = [
+            (indicators.staged, files.staged, Color::Green),
+            (indicators.modified, files.modified, Color::Yellow),
+            (indicators.deleted, files.deleted, Color::Yellow),
+            (indicators.renamed, files.renamed, Color::Cyan),
+            (indicators.untracked, files.untracked, Color::Magenta),
+            (indicators.unmerged, files.unmerged, Color::Red),
+        ];
+        for (file_indicator, count, color) in file_indicators {
+            if count == 0 {
+                continue;
+            }
+            let s = match self.show_file_counts {
+                true => format!("{}{}", file_indicator, count),
+                false => file_indicator.to_string(),
+            };
+            parts.push((self.terminal_color.color(&s, color), self.show_file_counts));
         }
 
-        if git_response[0].contains("[behind") {
-            let s = self.terminal_color.color(indicators.behind, Color::Yellow);
-            indicator.push_str(&s);
-            project_state.set(Information::Warning);
+        let unpushed_branches = status.unpushed_branches();
+        if !unpushed_branches.is_empty() {
+            let count = unpushed_branches.len() as u32;
+            let s = self.count_variant(indicators.branches, indicators.branches_count, count);
+            parts.push((self.terminal_color.color(&s, Color::Yellow), self.show_counts));
+        }
 
+        let dirty_submodules = status.submodules.iter().filter(|v| !v.is_clean()).count() as u32;
+        if dirty_submodules > 0 {
+            let s = self.count_variant(indicators.submodules, indicators.submodules_count, dirty_submodules);
+            parts.push((self.terminal_color.color(&s, Color::Yellow), self.show_counts));
         }
 
-        if git_response.len() > 1 {
-            let s = self.terminal_color.color(indicators.files, Color::Yellow);
-            indicator.push_str(&s);
-            project_state.set(Information::Warning);
+        // Only the verbose modes show the number of stash entries, e.g. `[⚑3]`
+        if status.stashes > 0 {
+            let show_count = self.verbose_mode != VerboseMode::Nothing;
+            let s = match show_count {
+                true => Indicators::with_count(indicators.stash_count, status.stashes),
+                false => indicators.stash.to_string(),
+            };
+            parts.push((self.terminal_color.color(&s, Color::Cyan), show_count));
         }
 
         if repo_ok {
-            let s = self.terminal_color.color(indicators.ok, Color::Green);
-            indicator.push_str(&s);
+            parts.push((self.terminal_color.color(indicators.ok, Color::Green), false));
         }
 
+        // The remote-tracking refs weren't updated, so ahead/behind could be outdated
+        if self.stale_remote {
+            parts.push((self.terminal_color.color(indicators.stale, Color::Yellow), false));
+        }
+
+        let mut indicator = String::from("[");
+        for (idx, (part, _)) in parts.iter().enumerate() {
+            // Keeps a count apart from the following indicator, e.g. `[→3 ←12 ◎]`
+            if idx > 0 && parts[idx - 1].1 {
+                indicator.push(' ');
+            }
+            indicator.push_str(part);
+        }
         indicator.push(']');
 
-        let path_variant = self.path_variant(path);
-        println!("{} - {}", indicator, path_variant);
+        // Linked worktrees are listed below their main repository
+        let nesting = if repository.nested { " └─" } else { "" };
+        let path_variant = self.repository_variant(repository);
+        let line = format!("{}{} - {}", nesting, indicator, path_variant);
+        if skipped {
+            self.skipped_main = Some(line);
+            return;
+        }
+        self.print_project(line, repository);
+
+        if self.verbose_mode == VerboseMode::Verbose2 {
+            self.render_branches(&unpushed_branches, indicators);
+        }
+        self.render_submodules(&status.submodules, indicators);
+    }
+
+    // Bare repositories only know, whether commits were fetched, which are not on any local
+    // branch yet, e.g. `[▣←3] - project.git (updated 2h ago)`. Mirrors are never behind.
+    pub fn render_bare(&mut self, status: &RepoStatus, repository: &Repository, indicators: &Indicators) {
+        let repo_ok = status.is_clean();
+        let skipped = self.verbose_mode == VerboseMode::Nothing && repo_ok;
+
+        let mut indicator = self.terminal_color.color(indicators.bare, Color::Cyan);
+        if status.behind > 0 {
+            let s = self.count_variant(indicators.behind, indicators.behind_count, status.behind);
+            indicator.push_str(&self.terminal_color.color(&s, Color::Yellow));
+        }
+        if status.is_detached() {
+            indicator.push_str(&self.terminal_color.color(indicators.detached, Color::Yellow));
+        }
+        if repo_ok {
+            indicator.push_str(&self.terminal_color.color(indicators.ok, Color::Green));
+        }
+        if self.stale_remote {
+            if status.behind > 0 && self.show_counts {
+                indicator.push(' ');
+            }
+            indicator.push_str(&self.terminal_color.color(indicators.stale, Color::Yellow));
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|v| v.as_secs()).unwrap_or(0);
+        let updated = match status.last_updated {
+            Some(v) => format!("updated {} ago", format_age(now.saturating_sub(v))),
+            None => "never updated".to_string(),
+        };
+        let path_variant = self.repository_variant(repository);
+        let line = format!("[{}] - {} ({})", indicator, path_variant, updated);
+        if skipped {
+            self.skipped_main = Some(line);
+            return;
+        }
+        self.print_project(line, repository);
+    }
+
+    // Prints the skipped main repository first, if `repository` is one of its linked worktrees
+    fn print_project(&mut self, line: String, repository: &Repository) {
+        let skipped_main = self.skipped_main.take();
+        if let Some(main) = skipped_main.filter(|_| repository.nested) {
+            println!("{}", main);
+        }
+        println!("{}", line);
+    }
+
+    // Submodules with changes are always listed below their superproject, the others
+    // only in the verbose modes
+    fn render_submodules(&self, submodules: &[SubmoduleStatus], indicators: &Indicators) {
+        for submodule in submodules {
+            if submodule.is_clean() && self.verbose_mode == VerboseMode::Nothing {
+                continue;
+            }
+
+            let mut indicator = String::new();
+            if !submodule.initialized {
+                indicator.push_str(&self.terminal_color.color(indicators.submodule_uninitialized, Color::Yellow));
+            }
+            if submodule.commit_changed {
+                indicator.push_str(&self.terminal_color.color(indicators.submodule_commit, Color::Yellow));
+            }
+            if submodule.modified {
+                indicator.push_str(&self.terminal_color.color(indicators.modified, Color::Yellow));
+            }
+            if submodule.untracked {
+                indicator.push_str(&self.terminal_color.color(indicators.untracked, Color::Magenta));
+            }
+            if submodule.is_clean() {
+                indicator.push_str(&self.terminal_color.color(indicators.ok, Color::Green));
+            }
+            println!(" └─[{}] - {}", indicator, submodule.path);
+        }
+    }
+
+    fn render_branches(&self, branches: &[&BranchStatus], indicators: &Indicators) {
+        for (idx, branch) in branches.iter().enumerate() {
+            let mut parts = vec![];
+            if branch.ahead > 0 {
+                parts.push(self.count_variant(indicators.ahead, indicators.ahead_count, branch.ahead));
+            }
+            if branch.behind > 0 {
+                parts.push(self.count_variant(indicators.behind, indicators.behind_count, branch.behind));
+            }
+            if branch.never_pushed() {
+                let s = match branch.local_commits {
+                    Some(v) if v > 0 => self.count_variant(indicators.no_upstream, indicators.no_upstream_count, v),
+                    _ => indicators.no_upstream.to_string(),
+                };
+                parts.push(s);
+            }
+
+            let separator = if self.show_counts { " " } else { "" };
+            let indicator = self.terminal_color.color(&parts.join(separator), Color::Yellow);
+            let tree = if idx + 1 == branches.len() { "└─" } else { "├─" };
+            println!(" {} [{}] {}", tree, indicator, branch.name);
+        }
     }
 
-    pub fn render_ok_msg(&self, msg: &str, indicators: &Indicators) -> () {
-        let indicator_fmt = format!("{}", indicators.ok);
-        let indicator = self.terminal_color.color(&indicator_fmt, Color::Green);
+    pub fn render_ok_msg(&self, msg: &str, indicators: &Indicators) {
+        if self.verbose_mode == VerboseMode::Quiet {
+            return;
+        }
+        let indicator = self.terminal_color.color(indicators.ok, Color::Green);
         // To stderr, because it's more a diagnostic information
         eprintln!("[{}] {}", indicator, msg);
     }
 
-    pub fn render_err(&self, err_msg: &str, indicators: Option<&Indicators>, path: Option<&PathBuf>) -> () {
+    // Watch mode redraws all projects in place
+    pub fn clear_screen(&self) {
+        print!("\x1b[2J\x1b[H");
+    }
+
+    pub fn render_watch_footer(&self, projects: usize, since_fetch: Option<Duration>) {
+        let fetched = match since_fetch {
+            Some(v) => format!("Remotes fetched {} ago", format_age(v.as_secs())),
+            None => "Remotes are not fetched".to_string(),
+        };
+        eprintln!("\nWatching {} projects. {}. Press Ctrl+C to quit", projects, fetched);
+    }
+
+    // A single line at the end of each run, e.g. `12 projects: 9 clean, 2 dirty, 1 ahead, 0 behind`
+    pub fn render_summary(&self, summary: &Summary) {
+        if self.verbose_mode == VerboseMode::Quiet {
+            return;
+        }
+        let mut counts = vec![
+            format!("{} clean", summary.clean),
+            format!("{} dirty", summary.dirty),
+            format!("{} ahead", summary.ahead),
+            format!("{} behind", summary.behind),
+        ];
+        // Problems are only listed, if there are any
+        let problems = [
+            (summary.fetch_failed, "fetch failed", "fetches failed"),
+            (summary.errored, "error", "errors"),
+            (summary.invalid_paths, "invalid path", "invalid paths"),
+        ];
+        for (count, singular, plural) in problems {
+            if count > 0 {
+                let label = if count == 1 { singular } else { plural };
+                counts.push(self.terminal_color.color(&format!("{} {}", count, label), Color::Red));
+            }
+        }
+        let projects = if summary.projects == 1 { "project" } else { "projects" };
+        eprintln!("{} {}: {}", summary.projects, projects, counts.join(", "));
+    }
+
+    pub fn render_stale_msg(&self, msg: &str, indicators: &Indicators) {
+        if self.verbose_mode == VerboseMode::Quiet {
+            return;
+        }
+        let indicator = self.terminal_color.color(indicators.stale, Color::Yellow);
+        eprintln!("[{}] {}", indicator, msg);
+    }
+
+    pub fn render_err(&self, err_msg: &str, indicators: Option<&Indicators>, path: Option<&Path>) {
+        if self.verbose_mode == VerboseMode::Quiet {
+            return;
+        }
         let indicator = match indicators {
             Some(v) => {
-                self.terminal_color.color(format!("{}", v.err).as_str(), Color::Red)
+                self.terminal_color.color(v.err, Color::Red)
             },
             None => "".to_string(),
         };
         match path {
             Some(v) => {
                 eprintln!("[{}] - {}\n └─■ Err: {}",
-                        indicator, &v.parent().unwrap().to_str().unwrap(), err_msg);
+                        indicator, &v.to_str().unwrap(), err_msg);
             },
             None => {
                 eprintln!("■ Err: {}", err_msg);
@@ -105,26 +349,89 @@ impl TerminalDisplay {
         };
     }
 
-    pub fn render_path_err(&self, err_msg: &str, indicators: &Indicators, path: &PathBuf) -> () {
-        let indicator = self.terminal_color.color(format!("{}", indicators.err).as_str(), Color::Red);
+    pub fn render_timeout(&self, timeout: Duration, indicators: &Indicators, path: &Path) {
+        let indicator = self.terminal_color.color(indicators.timeout, Color::Red);
+        eprintln!("[{}] - {}\n └─■ Err: Fetch timed out after {}s",
+                  indicator, path.to_str().unwrap(), timeout.as_secs());
+    }
+
+    pub fn render_path_err(&self, err_msg: &str, indicators: &Indicators, path: &Path) {
+        if self.verbose_mode == VerboseMode::Quiet {
+            return;
+        }
+        let indicator = self.terminal_color.color(indicators.err, Color::Red);
         eprintln!("[{}] - {}\n └─■ Err: {}",
                         indicator, &path.to_str().unwrap(), err_msg);
     }
 
 
-    fn path_variant(self, path: &PathBuf) -> &str {
+    fn count_variant(&self, indicator: &str, count_slot: &str, count: u32) -> String {
+        match self.show_counts {
+            true => Indicators::with_count(count_slot, count),
+            false => indicator.to_string(),
+        }
+    }
+
+    fn path_variant<'a>(&self, path: &'a Path) -> &'a str {
         match self.verbose_mode {
-            VerboseMode::Nothing => self.display_project_name(path),
+            VerboseMode::Nothing | VerboseMode::Quiet => self.display_project_name(path),
             VerboseMode::Verbose1 => self.display_project_name(path),
             VerboseMode::Verbose2 => self.display_full_path(path),
         }
     }
 
-    fn display_project_name(self, path: &PathBuf) -> &str {
-        path.parent().unwrap().file_name().unwrap().to_str().unwrap()
+    // Like `path_variant`, but `-vv` also shows where a symlinked repository is located
+    fn repository_variant(&self, repository: &Repository) -> String {
+        let path_variant = self.path_variant(&repository.work_tree);
+        match self.verbose_mode == VerboseMode::Verbose2 && repository.is_symlinked() {
+            true => format!("{} -> {}", path_variant, repository.canonical_work_tree.display()),
+            false => path_variant.to_string(),
+        }
+    }
+
+    fn display_project_name<'a>(&self, path: &'a Path) -> &'a str {
+        path.file_name().unwrap().to_str().unwrap()
+    }
+
+    fn display_full_path<'a>(&self, path: &'a Path) -> &'a str {
+        path.to_str().unwrap()
+    }
+}
+
+// The largest fitting unit of `age` in seconds, e.g. `45s`, `12m`, `3h` or `5d`
+fn format_age(age: u64) -> String {
+    match age {
+        0..=59 => format!("{}s", age),
+        60..=3599 => format!("{}m", age / 60),
+        3600..=86399 => format!("{}h", age / 3600),
+        _ => format!("{}d", age / 86400),
+    }
+}
+
+pub struct JsonDisplay {
+    pub output_format: OutputFormat,
+    reports: Vec<RepoReport>,
+}
+
+impl JsonDisplay {
+    pub fn new(output_format: OutputFormat) -> JsonDisplay {
+        JsonDisplay {
+            output_format,
+            reports: vec![],
+        }
+    }
+
+    pub fn render_report(&mut self, report: RepoReport) {
+        match self.output_format {
+            OutputFormat::Ndjson => println!("{}", serde_json::to_string(&report).unwrap()),
+            OutputFormat::Json => self.reports.push(report),
+            OutputFormat::Text => (),
+        }
     }
 
-    fn display_full_path(self, path: &PathBuf) -> &str {
-        path.parent().unwrap().to_str().unwrap()
+    pub fn finish(&mut self) {
+        if self.output_format == OutputFormat::Json {
+            println!("{}", serde_json::to_string_pretty(&self.reports).unwrap());
+        }
     }
 }